@@ -0,0 +1,44 @@
+use std::sync::Arc;
+
+use chrono::{DateTime, Utc};
+
+/// Source of the current time, so that everything time-dependent can be driven by a fake clock
+pub trait Clock: Send + Sync {
+    fn now(&self) -> DateTime<Utc>;
+}
+
+/// The clock managed as Tauri state, shared by the commands and background tasks
+pub type SharedClock = Arc<dyn Clock>;
+
+/// The real wall clock
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> DateTime<Utc> {
+        Utc::now()
+    }
+}
+
+/// A clock following tokio's time from a fixed starting point, for tests that pause tokio's time
+#[cfg(test)]
+pub struct FakeClock {
+    start: DateTime<Utc>,
+    started_at: tokio::time::Instant,
+}
+
+#[cfg(test)]
+impl FakeClock {
+    pub fn starting_at(start: DateTime<Utc>) -> Self {
+        Self {
+            start,
+            started_at: tokio::time::Instant::now(),
+        }
+    }
+}
+
+#[cfg(test)]
+impl Clock for FakeClock {
+    fn now(&self) -> DateTime<Utc> {
+        self.start + chrono::Duration::from_std(self.started_at.elapsed()).unwrap()
+    }
+}
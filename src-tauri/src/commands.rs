@@ -1,57 +1,170 @@
 use std::collections::HashMap;
 
 use chrono::DateTime;
-use rodio::{cpal::traits::HostTrait, OutputStream, Sink};
 use tauri::{AppHandle, Manager};
 use tracing::{error, instrument, trace, warn};
 
-use crate::{sound::notification_audio, storage::AppState, structs::drink_point::DrinkPoint};
+use crate::{
+    clock::SharedClock,
+    i18n,
+    sound::{self, notification_audio},
+    stats,
+    storage::{self, AppState, DataRecovery, RecoveryReport},
+    structs::{
+        active_window::ActiveWindow,
+        beverage::{Beverage, WATER_ID},
+        caffeine::{CaffeineSettings, CaffeineStatus},
+        daily_goal::{DailyGoal, DailyProgress},
+        day_settings::DaySettings,
+        drink_point::DrinkPoint,
+        language::Language,
+        repeat_policy::RepeatPolicy,
+        settings::Settings,
+        tray_preset::TrayPreset,
+        volume_unit::VolumeUnit,
+    },
+};
+
+/// Bounds for the reminder interval, in seconds
+const MIN_REMINDER_INTERVAL: u32 = 5 * 60;
+const MAX_REMINDER_INTERVAL: u32 = 24 * 60 * 60;
+
+/// Formats an amount of seconds as a readable interval, e.g. "1 hour 30 minutes"
+fn humanize_interval(seconds: u32, language: Language) -> String {
+    let plural = |n: u32, unit: &str| {
+        if n == 1 {
+            i18n::text(language, &format!("interval-{unit}"))
+        } else {
+            i18n::format(
+                language,
+                &format!("interval-{unit}s"),
+                &[("n", n.to_string().as_str())],
+            )
+        }
+    };
+
+    match (seconds / 3600, seconds % 3600 / 60) {
+        (0, minutes) => plural(minutes, "minute"),
+        (hours, 0) => plural(hours, "hour"),
+        (hours, minutes) => i18n::format(
+            language,
+            "interval-hours-minutes",
+            &[
+                ("hours", plural(hours, "hour").as_str()),
+                ("minutes", plural(minutes, "minute").as_str()),
+            ],
+        ),
+    }
+}
 
 #[instrument(skip(app))]
 #[tauri::command]
 pub fn create_drink_notification(app: AppHandle) {
-    let device_count = rodio::cpal::default_host()
-        .output_devices()
-        .unwrap()
-        .count();
-    trace!("Output device count: {device_count}");
+    send_drink_reminder(&app, 0);
+}
 
-    if device_count > 0 {
-        tauri::async_runtime::spawn(async move {
-            let (_stream, stream_handle) = OutputStream::try_default().unwrap();
-            let sink = Sink::try_new(&stream_handle).unwrap();
+/// Sends a drink reminder. `repeat` is how many times the reminder has been repeated after
+/// being ignored, which makes it more insistent if the missed reminder policy allows it
+#[instrument(skip(app))]
+pub fn send_drink_reminder(app: &AppHandle, repeat: u8) {
+    let (reminder_interval, escalate, last_drink_timestamp, settings) = {
+        let state = app.state::<AppState>();
+        let app_state = state.0.read().unwrap();
+        (
+            app_state.reminder_interval,
+            app_state.missed_reminder_policy.escalate,
+            app_state.drink_history.last().map(|drink| drink.timestamp),
+            app_state.settings,
+        )
+    };
 
-            sink.append(notification_audio());
-            sink.sleep_until_end();
-        });
+    if settings.reminder_sound {
+        sound::play(notification_audio, settings.volume);
     }
 
+    // Repeated reminders tell how long it's actually been since the last drink
+    let since_last_drink = match last_drink_timestamp {
+        Some(timestamp) if repeat > 0 => {
+            let now = app.state::<SharedClock>().now();
+            u32::try_from(now.timestamp() - timestamp).unwrap_or(u32::MAX)
+        }
+        _ => reminder_interval,
+    };
+    let is_urgent = repeat > 0 && escalate;
+
+    let language = settings.language();
+    let (title_key, message_key) = if is_urgent {
+        ("reminder-urgent-title", "reminder-urgent-body")
+    } else {
+        ("reminder-title", "reminder-body")
+    };
+    let title = i18n::text(language, title_key);
+    let title = title.as_str();
+    let message = i18n::format(
+        language,
+        message_key,
+        &[(
+            "interval",
+            humanize_interval(since_last_drink, language).as_str(),
+        )],
+    );
+    let message = message.as_str();
+
+    let reminder_actions = crate::notification::reminder_actions(&settings);
+    let reminder_actions: Vec<(&str, &str)> = reminder_actions
+        .iter()
+        .map(|(action, label)| (*action, label.as_str()))
+        .collect();
+
     #[cfg(target_os = "macos")]
     {
-        if let Err(e) = mac_notification_sys::Notification::new()
-            .app_icon("")
-            .title("Time to drink!")
-            .message("It's been 1 hour since your last drink, time to drink again!")
-            .send()
-        {
-            error!("Failed to send drink notification: {e}");
-        }
+        crate::notification::macos::send_reminder(app, title, message, &reminder_actions);
     }
 
     #[cfg(target_os = "windows")]
     {
-        if let Err(e) = winrt_notification::Toast::new(&app.config().tauri.bundle.identifier)
-            .title("Time to drink!")
-            .text1("It's been 1 hour since your last drink, time to drink again!")
-            .duration(winrt_notification::Duration::Short)
-            .sound(None)
-            .show()
-        {
+        if let Err(e) = crate::notification::winrt::send_reminder(
+            app,
+            &app.config().tauri.bundle.identifier,
+            title,
+            message,
+            &reminder_actions,
+            is_urgent,
+        ) {
             error!("Failed to send drink notification: {e}");
         }
     }
 
-    // TODO: Add Linux support
+    #[cfg(target_os = "linux")]
+    {
+        use crate::notification::{
+            linux::{send_to_session, Notification, Urgency},
+            OPEN_ACTION,
+        };
+
+        let open_label = i18n::text(language, "notification-open");
+        let actions: Vec<(&str, &str)> = std::iter::once((OPEN_ACTION, open_label.as_str()))
+            .chain(reminder_actions)
+            .collect();
+
+        if let Err(e) = send_to_session(
+            app,
+            &Notification {
+                summary: title,
+                body: message,
+                urgency: if is_urgent {
+                    Urgency::Critical
+                } else {
+                    Urgency::Normal
+                },
+                expire_timeout: 10_000,
+                actions: &actions,
+                is_reminder: true,
+            },
+        ) {
+            error!("Failed to send drink notification: {e}");
+        }
+    }
 }
 
 #[instrument(skip(app))]
@@ -135,25 +248,527 @@ pub fn list_drinks(state: tauri::State<AppState>) -> Vec<DrinkPoint> {
 
 #[instrument(skip(state))]
 #[tauri::command]
-pub fn list_drinks_group_day(state: tauri::State<AppState>) -> HashMap<String, f64> {
+pub fn list_drinks_group_day(state: tauri::State<AppState>) -> HashMap<String, stats::Total> {
     trace!("Sending drink data to FEnd");
 
-    let drink_history = state.0.read().unwrap().drink_history.clone();
+    let app_state = state.0.read().unwrap();
+
+    // Group drinks by day in the user's timezone, starting days at the configured time
+    stats::totals_per_date(
+        &app_state.drink_history,
+        &app_state.day_settings,
+        &app_state.beverages(),
+    )
+    .into_iter()
+    .map(|(date, total)| (date.to_string(), total))
+    .collect()
+}
+
+#[instrument(skip(state))]
+#[tauri::command]
+pub fn get_reminder_interval(state: tauri::State<AppState>) -> u32 {
+    state.0.read().unwrap().reminder_interval
+}
 
-    let mut grouped_drinks: HashMap<String, f64> = HashMap::new();
+#[instrument(skip(app))]
+#[tauri::command]
+pub fn set_reminder_interval(app: AppHandle, seconds: u32) -> Result<(), String> {
+    let state = app.state::<AppState>();
 
-    // Iterate through drinks, group drinks by day - DrinkPoint timestamp is set to 00:00:00
-    for point in &drink_history {
-        let local_datetime = DateTime::from_timestamp(point.timestamp, 0)
-            .unwrap()
-            .naive_local()
-            .date();
+    if !(MIN_REMINDER_INTERVAL..=MAX_REMINDER_INTERVAL).contains(&seconds) {
+        let language = state.0.read().unwrap().settings.language();
+        return Err(format!(
+            "Reminder interval must be between {} and {}",
+            humanize_interval(MIN_REMINDER_INTERVAL, language),
+            humanize_interval(MAX_REMINDER_INTERVAL, language)
+        ));
+    }
 
-        let entry = grouped_drinks
-            .entry(local_datetime.to_string())
-            .or_insert(0.0);
-        *entry += point.amount;
+    // Update the interval & drop the lock
+    {
+        let mut app_state = state.0.write().unwrap();
+        app_state.reminder_interval = seconds;
     }
 
-    grouped_drinks
+    storage::save_app_state(&state.0.read().unwrap()).map_err(|e| e.to_string())?;
+
+    app.emit_all("interval-changed", seconds).unwrap();
+    app.trigger_global("interval-changed", None);
+
+    Ok(())
+}
+
+#[instrument(skip(state))]
+#[tauri::command]
+pub fn get_reminder_schedule(state: tauri::State<AppState>) -> Vec<ActiveWindow> {
+    state.0.read().unwrap().reminder_schedule.clone()
+}
+
+#[instrument(skip(app))]
+#[tauri::command]
+pub fn set_reminder_schedule(app: AppHandle, schedule: Vec<ActiveWindow>) -> Result<(), String> {
+    for window in &schedule {
+        window.validate()?;
+    }
+
+    let state = app.state::<AppState>();
+
+    // Update the schedule & drop the lock
+    {
+        let mut app_state = state.0.write().unwrap();
+        app_state.reminder_schedule = schedule.clone();
+    }
+
+    storage::save_app_state(&state.0.read().unwrap()).map_err(|e| e.to_string())?;
+
+    app.emit_all("schedule-changed", schedule).unwrap();
+    app.trigger_global("schedule-changed", None);
+
+    Ok(())
+}
+
+#[instrument(skip(state))]
+#[tauri::command]
+pub fn get_paused_until(state: tauri::State<AppState>) -> Option<i64> {
+    state.0.read().unwrap().paused_until
+}
+
+#[instrument(skip(app))]
+#[tauri::command]
+pub fn snooze_reminders(app: AppHandle, minutes: u32) -> Result<(), String> {
+    if minutes == 0 || minutes > MAX_REMINDER_INTERVAL / 60 {
+        let state = app.state::<AppState>();
+        let language = state.0.read().unwrap().settings.language();
+        return Err(format!(
+            "Snooze duration must be between 1 minute and {}",
+            humanize_interval(MAX_REMINDER_INTERVAL, language)
+        ));
+    }
+
+    crate::snooze(&app, minutes);
+
+    Ok(())
+}
+
+#[instrument(skip(app))]
+#[tauri::command]
+pub fn pause_reminders_until_tomorrow(app: AppHandle) {
+    crate::pause_until_tomorrow(&app);
+}
+
+#[instrument(skip(app))]
+#[tauri::command]
+pub fn resume_reminders(app: AppHandle) {
+    crate::set_paused_until(&app, None);
+}
+
+#[instrument(skip(state))]
+#[tauri::command]
+pub fn get_missed_reminder_policy(state: tauri::State<AppState>) -> RepeatPolicy {
+    state.0.read().unwrap().missed_reminder_policy
+}
+
+#[instrument(skip(app))]
+#[tauri::command]
+pub fn set_missed_reminder_policy(app: AppHandle, policy: RepeatPolicy) -> Result<(), String> {
+    policy.validate()?;
+
+    let state = app.state::<AppState>();
+
+    // Update the policy & drop the lock
+    {
+        let mut app_state = state.0.write().unwrap();
+        app_state.missed_reminder_policy = policy;
+    }
+
+    storage::save_app_state(&state.0.read().unwrap()).map_err(|e| e.to_string())?;
+
+    app.emit_all("repeat-policy-changed", policy).unwrap();
+    app.trigger_global("repeat-policy-changed", None);
+
+    Ok(())
+}
+
+#[instrument(skip(state))]
+#[tauri::command]
+pub fn get_daily_goal(state: tauri::State<AppState>) -> DailyGoal {
+    state.0.read().unwrap().daily_goal
+}
+
+#[instrument(skip(app))]
+#[tauri::command]
+pub fn set_daily_goal(app: AppHandle, goal: DailyGoal) -> Result<(), String> {
+    goal.validate()?;
+
+    let state = app.state::<AppState>();
+
+    // Update the goal & drop the lock
+    {
+        let mut app_state = state.0.write().unwrap();
+        app_state.daily_goal = goal;
+    }
+
+    storage::save_app_state(&state.0.read().unwrap()).map_err(|e| e.to_string())?;
+
+    app.emit_all("goal-changed", goal).unwrap();
+
+    Ok(())
+}
+
+#[instrument(skip(app))]
+#[tauri::command]
+pub fn get_daily_progress(app: AppHandle) -> DailyProgress {
+    trace!("Sending daily progress to FEnd");
+
+    let now = app.state::<SharedClock>().now();
+
+    let state = app.state::<AppState>();
+    let app_state = state.0.read().unwrap();
+    let today = app_state.day_settings.date_of(now);
+
+    DailyProgress::new(
+        app_state.daily_goal.amount,
+        stats::total_on_date(
+            &app_state.drink_history,
+            today,
+            &app_state.day_settings,
+            &app_state.beverages(),
+        ),
+    )
+}
+
+/// How the save file was recovered at startup, `None` if it loaded fine
+#[instrument(skip(state))]
+#[tauri::command]
+pub fn get_data_recovery(state: tauri::State<DataRecovery>) -> Option<RecoveryReport> {
+    state.0.clone()
+}
+
+#[instrument(skip(state))]
+#[tauri::command]
+pub fn get_day_settings(state: tauri::State<AppState>) -> DaySettings {
+    state.0.read().unwrap().day_settings.clone()
+}
+
+#[instrument(skip(app))]
+#[tauri::command]
+pub fn set_day_settings(app: AppHandle, day_settings: DaySettings) -> Result<(), String> {
+    day_settings.validate()?;
+
+    let state = app.state::<AppState>();
+
+    // Update the day settings & drop the lock
+    {
+        let mut app_state = state.0.write().unwrap();
+        app_state.day_settings = day_settings.clone();
+    }
+
+    storage::save_app_state(&state.0.read().unwrap()).map_err(|e| e.to_string())?;
+
+    app.emit_all("day-settings-changed", day_settings).unwrap();
+    app.trigger_global("day-settings-changed", None);
+
+    Ok(())
+}
+
+#[instrument(skip(state))]
+#[tauri::command]
+pub fn get_settings(state: tauri::State<AppState>) -> Settings {
+    state.0.read().unwrap().settings
+}
+
+#[instrument(skip(app))]
+#[tauri::command]
+pub fn update_settings(app: AppHandle, settings: Settings) -> Result<(), String> {
+    settings.validate()?;
+
+    let state = app.state::<AppState>();
+
+    // Update the settings & drop the lock
+    {
+        let mut app_state = state.0.write().unwrap();
+        app_state.settings = settings;
+    }
+
+    storage::save_app_state(&state.0.read().unwrap()).map_err(|e| e.to_string())?;
+
+    app.emit_all("settings-changed", settings).unwrap();
+    app.trigger_global("settings-changed", None);
+
+    Ok(())
+}
+
+/// Formats milliliters in the unit chosen in the settings, the same way the tray & notifications do
+#[tauri::command]
+pub fn format_volume(state: tauri::State<AppState>, amount: f64) -> String {
+    let settings = state.0.read().unwrap().settings;
+    settings.unit.format(amount, settings.language())
+}
+
+/// Converts an amount between units, e.g. to turn what the user typed into milliliters
+#[tauri::command]
+pub fn convert_volume(amount: f64, from: VolumeUnit, to: VolumeUnit) -> f64 {
+    to.from_ml(from.to_ml(amount))
+}
+
+#[instrument(skip(state))]
+#[tauri::command]
+pub fn get_caffeine_settings(state: tauri::State<AppState>) -> CaffeineSettings {
+    state.0.read().unwrap().caffeine
+}
+
+#[instrument(skip(app))]
+#[tauri::command]
+pub fn set_caffeine_settings(app: AppHandle, caffeine: CaffeineSettings) -> Result<(), String> {
+    caffeine.validate()?;
+
+    let state = app.state::<AppState>();
+
+    // Update the caffeine settings & drop the lock
+    {
+        let mut app_state = state.0.write().unwrap();
+        app_state.caffeine = caffeine;
+    }
+
+    storage::save_app_state(&state.0.read().unwrap()).map_err(|e| e.to_string())?;
+
+    app.emit_all("caffeine-settings-changed", caffeine).unwrap();
+
+    Ok(())
+}
+
+#[instrument(skip(app))]
+#[tauri::command]
+pub fn get_caffeine_status(app: AppHandle) -> CaffeineStatus {
+    trace!("Sending caffeine status to FEnd");
+
+    let now = app.state::<SharedClock>().now();
+
+    let state = app.state::<AppState>();
+    let app_state = state.0.read().unwrap();
+    let today = app_state.day_settings.date_of(now);
+    let beverages = app_state.beverages();
+
+    CaffeineStatus {
+        today: stats::total_on_date(
+            &app_state.drink_history,
+            today,
+            &app_state.day_settings,
+            &beverages,
+        )
+        .caffeine,
+        in_body: stats::caffeine_in_body(
+            &app_state.drink_history,
+            &beverages,
+            app_state.caffeine.half_life,
+            now,
+        ),
+        daily_limit: app_state.caffeine.daily_limit,
+    }
+}
+
+/// Built-in beverages followed by the user's own
+#[instrument(skip(state))]
+#[tauri::command]
+pub fn list_beverages(state: tauri::State<AppState>) -> Vec<Beverage> {
+    state.0.read().unwrap().beverages()
+}
+
+/// Replaces the user's own beverages. Drinks of a removed beverage count as water from then on
+#[instrument(skip(app))]
+#[tauri::command]
+pub fn set_custom_beverages(app: AppHandle, beverages: Vec<Beverage>) -> Result<(), String> {
+    for (i, beverage) in beverages.iter().enumerate() {
+        beverage.validate()?;
+        if beverages[..i].iter().any(|other| other.id == beverage.id) {
+            return Err(format!(
+                "Beverage ID {} is used more than once",
+                beverage.id
+            ));
+        }
+    }
+
+    let state = app.state::<AppState>();
+
+    // Update the beverages & drop the lock
+    let all_beverages = {
+        let mut app_state = state.0.write().unwrap();
+        app_state.custom_beverages = beverages;
+        app_state.beverages()
+    };
+
+    storage::save_app_state(&state.0.read().unwrap()).map_err(|e| e.to_string())?;
+
+    crate::refresh_tray_menu(&app);
+
+    app.emit_all("beverages-changed", all_beverages).unwrap();
+    app.trigger_global("beverages-changed", None);
+
+    Ok(())
+}
+
+#[instrument(skip(state))]
+#[tauri::command]
+pub fn get_tray_presets(state: tauri::State<AppState>) -> Vec<TrayPreset> {
+    let app_state = state.0.read().unwrap();
+    let language = app_state.settings.language();
+
+    // Default labels are sent translated, as they appear in the tray
+    app_state
+        .tray_presets
+        .iter()
+        .map(|preset| TrayPreset {
+            label: i18n::preset_label(language, preset),
+            ..preset.clone()
+        })
+        .collect()
+}
+
+/// Replaces the tray's quick-add items, in the order they're shown
+#[instrument(skip(app))]
+#[tauri::command]
+pub fn set_tray_presets(app: AppHandle, presets: Vec<TrayPreset>) -> Result<(), String> {
+    let state = app.state::<AppState>();
+
+    // Update the presets & drop the lock
+    {
+        let mut app_state = state.0.write().unwrap();
+        for (i, preset) in presets.iter().enumerate() {
+            preset.validate()?;
+            check_beverage_exists(&app_state, preset.beverage)?;
+            if presets[..i].iter().any(|other| other.id == preset.id) {
+                return Err(format!("Preset ID {} is used more than once", preset.id));
+            }
+        }
+
+        app_state.tray_presets = presets.clone();
+    }
+
+    storage::save_app_state(&state.0.read().unwrap()).map_err(|e| e.to_string())?;
+
+    crate::refresh_tray_menu(&app);
+
+    app.emit_all("tray-presets-changed", presets).unwrap();
+
+    Ok(())
+}
+
+/// Fails if there's no beverage with the given ID
+fn check_beverage_exists(app_state: &storage::InnerAppState, id: u32) -> Result<(), String> {
+    if app_state
+        .beverages()
+        .iter()
+        .any(|beverage| beverage.id == id)
+    {
+        Ok(())
+    } else {
+        Err(format!("No beverage with ID {id}"))
+    }
+}
+
+#[instrument(skip(app))]
+#[tauri::command]
+pub fn delete_drink(app: AppHandle, id: u64) -> Result<DrinkPoint, String> {
+    let state = app.state::<AppState>();
+
+    let removed = state
+        .0
+        .write()
+        .unwrap()
+        .remove_drink(id)
+        .ok_or_else(|| format!("No drink with ID {id}"))?;
+
+    crate::drink_history_changed(&app)?;
+
+    Ok(removed)
+}
+
+#[instrument(skip(app))]
+#[tauri::command]
+pub fn edit_drink(
+    app: AppHandle,
+    id: u64,
+    amount: Option<f64>,
+    timestamp: Option<i64>,
+    beverage: Option<u32>,
+    caffeine: Option<f64>,
+    clear_caffeine: Option<bool>,
+) -> Result<DrinkPoint, String> {
+    let now = app.state::<SharedClock>().now();
+    let state = app.state::<AppState>();
+
+    // A missing `caffeine` leaves the override as is, so removing it takes a flag of its own
+    let clear_caffeine = clear_caffeine.unwrap_or(false);
+    if clear_caffeine && caffeine.is_some() {
+        return Err("Can't both set and clear the drink's caffeine".to_owned());
+    }
+
+    // Update the drink & drop the lock
+    let edited = {
+        let mut app_state = state.0.write().unwrap();
+        if let Some(beverage) = beverage {
+            check_beverage_exists(&app_state, beverage)?;
+        }
+
+        let drink = app_state
+            .drink_history
+            .iter_mut()
+            .find(|drink| drink.id == id)
+            .ok_or_else(|| format!("No drink with ID {id}"))?;
+
+        let mut edited = *drink;
+        edited.amount = amount.unwrap_or(edited.amount);
+        edited.timestamp = timestamp.unwrap_or(edited.timestamp);
+        edited.beverage = beverage.unwrap_or(edited.beverage);
+        edited.caffeine = if clear_caffeine {
+            None
+        } else {
+            caffeine.or(edited.caffeine)
+        };
+        edited.validate(now)?;
+        *drink = edited;
+
+        // Keep the history in chronological order
+        app_state.drink_history.sort_by_key(|drink| drink.timestamp);
+
+        edited
+    };
+
+    crate::drink_history_changed(&app)?;
+
+    Ok(edited)
+}
+
+#[instrument(skip(app))]
+#[tauri::command]
+pub fn undo_last_drink(app: AppHandle) -> Result<Option<DrinkPoint>, String> {
+    crate::undo_last_drink(&app)
+}
+
+/// Logs a drink of any amount, optionally at a time in the past. Drinks are water by default, and
+/// have the beverage's caffeine unless `caffeine` is given
+#[instrument(skip(app))]
+#[tauri::command]
+pub fn log_drink(
+    app: AppHandle,
+    amount: f64,
+    timestamp: Option<i64>,
+    beverage: Option<u32>,
+    caffeine: Option<f64>,
+) -> Result<DrinkPoint, String> {
+    let now = app.state::<SharedClock>().now();
+    let time = match timestamp {
+        Some(timestamp) => {
+            DateTime::from_timestamp(timestamp, 0).ok_or("Invalid drink timestamp")?
+        }
+        None => now,
+    };
+
+    let beverage = beverage.unwrap_or(WATER_ID);
+    check_beverage_exists(&app.state::<AppState>().0.read().unwrap(), beverage)?;
+    let mut drink = DrinkPoint::new(amount, beverage, &time);
+    drink.caffeine = caffeine;
+    drink.validate(now)?;
+
+    crate::record_drink(&app, amount, beverage, caffeine, time)
 }
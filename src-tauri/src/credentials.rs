@@ -0,0 +1,249 @@
+use std::{collections::HashMap, fmt, path::PathBuf};
+
+use aes_gcm::{
+    aead::{Aead, KeyInit},
+    Aes256Gcm, Key, Nonce,
+};
+use rand::RngCore;
+use tracing::{instrument, trace, warn};
+
+use crate::{storage, storage::PROJECT_DIR, PROJECT_IDENTIFIER};
+
+const NONCE_LENGTH: usize = 12;
+
+#[derive(Debug)]
+pub enum CredentialError {
+    Keyring(keyring::Error),
+    Io(std::io::Error),
+    /// The fallback file couldn't be decrypted or read
+    Corrupt,
+}
+
+impl fmt::Display for CredentialError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Keyring(e) => write!(f, "unable to use the OS keyring: {e}"),
+            Self::Io(e) => write!(f, "unable to use the credential file: {e}"),
+            Self::Corrupt => write!(f, "the credential file is corrupt"),
+        }
+    }
+}
+
+impl std::error::Error for CredentialError {}
+
+impl From<std::io::Error> for CredentialError {
+    fn from(e: std::io::Error) -> Self {
+        Self::Io(e)
+    }
+}
+
+/// Whether the keyring can't be used at all, as opposed to failing for this one credential
+fn is_keyring_unavailable(e: &keyring::Error) -> bool {
+    matches!(
+        e,
+        keyring::Error::NoStorageAccess(_) | keyring::Error::PlatformFailure(_)
+    )
+}
+
+/// Stores a secret, in the OS keyring if there is one or else in an encrypted file. The file is
+/// only obfuscated, see `fallback_key_path`
+#[instrument(skip(secret))]
+pub fn save(account: &str, secret: &str) -> Result<(), CredentialError> {
+    match keyring::Entry::new(PROJECT_IDENTIFIER, account).and_then(|e| e.set_password(secret)) {
+        Ok(()) => {
+            // Don't leave an older copy behind in the fallback file
+            if let Err(e) = update_fallback_file(|secrets| secrets.remove(account).is_some()) {
+                warn!("Unable to clean up the credential file: {e}");
+            }
+            Ok(())
+        }
+        Err(e) if is_keyring_unavailable(&e) => {
+            warn!("OS keyring unavailable, falling back to an encrypted file: {e}");
+            update_fallback_file(|secrets| {
+                secrets.insert(account.to_owned(), secret.to_owned());
+                true
+            })
+        }
+        Err(e) => Err(CredentialError::Keyring(e)),
+    }
+}
+
+#[instrument]
+pub fn load(account: &str) -> Result<Option<String>, CredentialError> {
+    match keyring::Entry::new(PROJECT_IDENTIFIER, account).and_then(|e| e.get_password()) {
+        Ok(secret) => return Ok(Some(secret)),
+        Err(keyring::Error::NoEntry) => {}
+        Err(e) if is_keyring_unavailable(&e) => {
+            warn!("OS keyring unavailable, reading the encrypted file: {e}");
+        }
+        Err(e) => return Err(CredentialError::Keyring(e)),
+    }
+
+    // The secret may have been saved while the keyring was unavailable
+    Ok(read_fallback_file()?.remove(account))
+}
+
+#[instrument]
+pub fn delete(account: &str) -> Result<(), CredentialError> {
+    match keyring::Entry::new(PROJECT_IDENTIFIER, account).and_then(|e| e.delete_password()) {
+        Ok(()) | Err(keyring::Error::NoEntry) => {}
+        Err(e) if is_keyring_unavailable(&e) => {}
+        Err(e) => return Err(CredentialError::Keyring(e)),
+    }
+
+    update_fallback_file(|secrets| secrets.remove(account).is_some())
+}
+
+fn fallback_file_path() -> PathBuf {
+    PROJECT_DIR.config_dir().join("credentials.bin")
+}
+
+/// The key lives apart from the credentials, in the machine-local data directory, so that
+/// copying or syncing the config directory doesn't leak usable secrets.
+///
+/// This is obfuscation, not protection: the key is stored unencrypted under the same user
+/// account, so anything that can read the user's files can decrypt the secrets. Only the OS
+/// keyring actually keeps them safe
+fn fallback_key_path() -> PathBuf {
+    PROJECT_DIR.data_local_dir().join("credentials.key")
+}
+
+/// Creates a file only the current user can read, before anything is written to it
+fn create_private(path: &std::path::Path) -> Result<std::fs::File, std::io::Error> {
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+        options.mode(0o600);
+
+        let file = options.open(path)?;
+        // The mode only applies to new files, a leftover one may still be readable by others
+        file.set_permissions(std::fs::Permissions::from_mode(0o600))?;
+        Ok(file)
+    }
+
+    #[cfg(not(unix))]
+    options.open(path)
+}
+
+fn write_private(path: &std::path::Path, data: &[u8]) -> Result<(), std::io::Error> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+
+    storage::write_atomically_with(path, data, create_private)
+}
+
+fn fallback_key(create: bool) -> Result<Option<Aes256Gcm>, CredentialError> {
+    let key_path = fallback_key_path();
+
+    let key = match std::fs::read(&key_path) {
+        Ok(key) if key.len() == 32 => key,
+        Ok(_) => return Err(CredentialError::Corrupt),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound && create => {
+            trace!("Creating credential file key");
+            let mut key = vec![0u8; 32];
+            rand::thread_rng().fill_bytes(&mut key);
+            write_private(&key_path, &key)?;
+            key
+        }
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e.into()),
+    };
+
+    Ok(Some(Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&key))))
+}
+
+fn read_fallback_file() -> Result<HashMap<String, String>, CredentialError> {
+    let data = match std::fs::read(fallback_file_path()) {
+        Ok(data) => data,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(HashMap::new()),
+        Err(e) => return Err(e.into()),
+    };
+
+    let Some(cipher) = fallback_key(false)? else {
+        return Err(CredentialError::Corrupt);
+    };
+    if data.len() < NONCE_LENGTH {
+        return Err(CredentialError::Corrupt);
+    }
+
+    let (nonce, ciphertext) = data.split_at(NONCE_LENGTH);
+    let plaintext = cipher
+        .decrypt(Nonce::from_slice(nonce), ciphertext)
+        .map_err(|_| CredentialError::Corrupt)?;
+
+    serde_json::from_slice(&plaintext).map_err(|_| CredentialError::Corrupt)
+}
+
+/// Applies `update` to the secrets in the fallback file, writing them back if it returns true
+fn update_fallback_file(
+    update: impl FnOnce(&mut HashMap<String, String>) -> bool,
+) -> Result<(), CredentialError> {
+    let mut secrets = read_fallback_file()?;
+    if !update(&mut secrets) {
+        return Ok(());
+    }
+
+    if secrets.is_empty() {
+        return match std::fs::remove_file(fallback_file_path()) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(()),
+        };
+    }
+
+    let cipher = fallback_key(true)?.expect("Credential file key was just created!");
+    let mut nonce = [0u8; NONCE_LENGTH];
+    rand::thread_rng().fill_bytes(&mut nonce);
+
+    let plaintext = serde_json::to_vec(&secrets).unwrap();
+    let ciphertext = cipher
+        .encrypt(Nonce::from_slice(&nonce), plaintext.as_slice())
+        .map_err(|_| CredentialError::Corrupt)?;
+
+    write_private(
+        &fallback_file_path(),
+        &[nonce.as_slice(), &ciphertext].concat(),
+    )?;
+
+    Ok(())
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use std::os::unix::fs::PermissionsExt;
+
+    use super::*;
+
+    fn mode(path: &std::path::Path) -> u32 {
+        std::fs::metadata(path).unwrap().permissions().mode() & 0o777
+    }
+
+    #[test]
+    fn private_files_are_only_readable_by_the_user() {
+        let dir = std::env::temp_dir().join(format!(
+            "hydrate-reminder-test-credentials-{}",
+            std::process::id()
+        ));
+        let _ = std::fs::remove_dir_all(&dir);
+        let path = dir.join("credentials.key");
+
+        // A temporary file left behind with the default permissions
+        std::fs::create_dir_all(&dir).unwrap();
+        let temp_path = dir.join("credentials.key.tmp");
+        std::fs::write(&temp_path, b"leftover").unwrap();
+        std::fs::set_permissions(&temp_path, std::fs::Permissions::from_mode(0o644)).unwrap();
+
+        write_private(&path, b"secret").unwrap();
+        assert_eq!(mode(&path), 0o600);
+        assert_eq!(std::fs::read(&path).unwrap(), b"secret");
+
+        std::fs::remove_file(&path).unwrap();
+        write_private(&path, b"new secret").unwrap();
+        assert_eq!(mode(&path), 0o600);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
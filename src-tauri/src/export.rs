@@ -0,0 +1,382 @@
+use std::path::PathBuf;
+
+use chrono::{DateTime, FixedOffset, NaiveDate};
+use serde::{Deserialize, Serialize};
+use tauri::{api::dialog::blocking::FileDialogBuilder, AppHandle, Manager};
+use tracing::{instrument, trace};
+
+use crate::{
+    i18n, stats,
+    storage::{self, AppState},
+    structs::{
+        beverage::{self, Beverage},
+        day_settings::DaySettings,
+        drink_point::DrinkPoint,
+    },
+};
+
+/// Unit all amounts are stored & exported in
+pub const AMOUNT_UNIT: &str = "ml";
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    Csv,
+    Json,
+}
+
+impl ExportFormat {
+    fn extension(self) -> &'static str {
+        match self {
+            Self::Csv => "csv",
+            Self::Json => "json",
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+pub struct ExportOptions {
+    pub format: ExportFormat,
+
+    /// First day to export, inclusive. Days are split according to the day settings
+    pub from: Option<NaiveDate>,
+
+    /// Last day to export, inclusive
+    pub to: Option<NaiveDate>,
+
+    /// Export the total per day, like `list_drinks_group_day`, instead of every drink
+    pub group_by_day: bool,
+}
+
+/// A drink as written to an export
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ExportedDrink {
+    /// ISO-8601 time in the timezone the drink was logged in
+    pub time: DateTime<FixedOffset>,
+    pub amount: f64,
+    pub unit: String,
+
+    /// Name of the beverage
+    #[serde(rename = "type")]
+    pub drink_type: String,
+
+    /// Amount weighted by the beverage's hydration coefficient
+    pub hydration: f64,
+}
+
+impl ExportedDrink {
+    fn new(drink: &DrinkPoint, beverages: &[Beverage]) -> Self {
+        let beverage = beverage::find(beverages, drink.beverage);
+        let offset = FixedOffset::east_opt(drink.utc_offset)
+            .unwrap_or_else(|| FixedOffset::east_opt(0).unwrap());
+
+        Self {
+            time: DateTime::from_timestamp(drink.timestamp, 0)
+                .unwrap_or_default()
+                .with_timezone(&offset),
+            amount: drink.amount,
+            unit: AMOUNT_UNIT.to_owned(),
+            drink_type: beverage.name.clone(),
+            hydration: drink.amount * beverage.hydration,
+        }
+    }
+}
+
+/// The total amount drank on a day, as written to an export
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ExportedDay {
+    pub date: NaiveDate,
+    pub amount: f64,
+    pub unit: String,
+
+    /// Amount weighted by each beverage's hydration coefficient
+    pub hydration: f64,
+}
+
+/// Quotes a CSV field if needed
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_owned()
+    }
+}
+
+/// Renders the drinks in `history` that fall within the options' date range
+pub fn render_export(
+    history: &[DrinkPoint],
+    day_settings: &DaySettings,
+    beverages: &[Beverage],
+    options: &ExportOptions,
+) -> String {
+    let drinks: Vec<DrinkPoint> = history
+        .iter()
+        .filter(|drink| {
+            let date = day_settings.date_of_drink(drink);
+            options.from.map_or(true, |from| date >= from)
+                && options.to.map_or(true, |to| date <= to)
+        })
+        .copied()
+        .collect();
+
+    if options.group_by_day {
+        let mut days: Vec<ExportedDay> = stats::totals_per_date(&drinks, day_settings, beverages)
+            .into_iter()
+            .map(|(date, total)| ExportedDay {
+                date,
+                amount: total.volume,
+                unit: AMOUNT_UNIT.to_owned(),
+                hydration: total.hydration,
+            })
+            .collect();
+        days.sort_by_key(|day| day.date);
+
+        match options.format {
+            ExportFormat::Csv => std::iter::once("date,amount,unit,hydration".to_owned())
+                .chain(days.iter().map(|day| {
+                    format!(
+                        "{},{},{},{}",
+                        day.date,
+                        day.amount,
+                        csv_field(&day.unit),
+                        day.hydration
+                    )
+                }))
+                .map(|line| line + "\n")
+                .collect(),
+            ExportFormat::Json => serde_json::to_string_pretty(&days).unwrap(),
+        }
+    } else {
+        let drinks: Vec<ExportedDrink> = drinks
+            .iter()
+            .map(|drink| ExportedDrink::new(drink, beverages))
+            .collect();
+
+        match options.format {
+            ExportFormat::Csv => std::iter::once("time,amount,unit,type,hydration".to_owned())
+                .chain(drinks.iter().map(|drink| {
+                    format!(
+                        "{},{},{},{},{}",
+                        drink.time.to_rfc3339(),
+                        drink.amount,
+                        csv_field(&drink.unit),
+                        csv_field(&drink.drink_type),
+                        drink.hydration
+                    )
+                }))
+                .map(|line| line + "\n")
+                .collect(),
+            ExportFormat::Json => serde_json::to_string_pretty(&drinks).unwrap(),
+        }
+    }
+}
+
+/// Asks where to save the export, then writes it there. Returns `None` if the user cancelled
+#[instrument(skip(app))]
+#[tauri::command]
+pub async fn export_drinks(
+    app: AppHandle,
+    options: ExportOptions,
+) -> Result<Option<PathBuf>, String> {
+    let extension = options.format.extension();
+    let language = app
+        .state::<AppState>()
+        .0
+        .read()
+        .unwrap()
+        .settings
+        .language();
+
+    // Blocks until the dialog is closed, which is fine outside of the main thread
+    let Some(path) = FileDialogBuilder::new()
+        .set_title(&i18n::text(language, "export-dialog-title"))
+        .set_file_name(&format!("hydrate-reminder-history.{extension}"))
+        .add_filter(extension.to_uppercase(), &[extension])
+        .save_file()
+    else {
+        trace!("Export cancelled");
+        return Ok(None);
+    };
+
+    let export = {
+        let state = app.state::<AppState>();
+        let app_state = state.0.read().unwrap();
+        render_export(
+            &app_state.drink_history,
+            &app_state.day_settings,
+            &app_state.beverages(),
+            &options,
+        )
+    };
+
+    storage::write_atomically(&path, export.as_bytes()).map_err(|e| e.to_string())?;
+    trace!("Exported drink history to {path:?}");
+
+    Ok(Some(path))
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::structs::beverage::{BUILT_IN_BEVERAGES, WATER_ID};
+
+    const COFFEE_ID: u32 = 2;
+
+    fn drink(time: &str, amount: f64, beverage: u32) -> DrinkPoint {
+        DrinkPoint::new(
+            amount,
+            beverage,
+            &DateTime::parse_from_rfc3339(time).unwrap(),
+        )
+    }
+
+    /// Two drinks on May 1st in UTC+2, then one on May 2nd in UTC+7 that's still May 1st in UTC
+    fn history() -> Vec<DrinkPoint> {
+        vec![
+            drink("2024-05-01T08:00:00+02:00", 250.0, WATER_ID),
+            drink("2024-05-01T23:30:00+02:00", 250.0, COFFEE_ID),
+            drink("2024-05-02T06:00:00+07:00", 300.0, WATER_ID),
+        ]
+    }
+
+    fn export(history: &[DrinkPoint], options: ExportOptions) -> String {
+        render_export(
+            history,
+            &DaySettings::default(),
+            &BUILT_IN_BEVERAGES,
+            &options,
+        )
+    }
+
+    fn options(format: ExportFormat, group_by_day: bool) -> ExportOptions {
+        ExportOptions {
+            format,
+            from: None,
+            to: None,
+            group_by_day,
+        }
+    }
+
+    fn date(day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(2024, 5, day).unwrap()
+    }
+
+    #[test]
+    fn drinks_are_rendered_as_csv() {
+        let mut beverages = BUILT_IN_BEVERAGES.clone();
+        beverages.push(Beverage {
+            id: 100,
+            name: "Tea, \"iced\"".to_owned(),
+            ..beverages[1].clone()
+        });
+        let mut history = history();
+        history.push(drink("2024-05-02T12:00:00+07:00", 400.0, 100));
+
+        let csv = render_export(
+            &history,
+            &DaySettings::default(),
+            &beverages,
+            &options(ExportFormat::Csv, false),
+        );
+
+        assert_eq!(
+            csv,
+            "time,amount,unit,type,hydration\n\
+            2024-05-01T08:00:00+02:00,250,ml,Water,250\n\
+            2024-05-01T23:30:00+02:00,250,ml,Coffee,200\n\
+            2024-05-02T06:00:00+07:00,300,ml,Water,300\n\
+            2024-05-02T12:00:00+07:00,400,ml,\"Tea, \"\"iced\"\"\",360\n"
+        );
+    }
+
+    #[test]
+    fn drinks_are_rendered_as_json() {
+        let exported: serde_json::Value =
+            serde_json::from_str(&export(&history(), options(ExportFormat::Json, false))).unwrap();
+
+        assert_eq!(
+            exported,
+            json!([
+                {
+                    "time": "2024-05-01T08:00:00+02:00",
+                    "amount": 250.0,
+                    "unit": "ml",
+                    "type": "Water",
+                    "hydration": 250.0,
+                },
+                {
+                    "time": "2024-05-01T23:30:00+02:00",
+                    "amount": 250.0,
+                    "unit": "ml",
+                    "type": "Coffee",
+                    "hydration": 200.0,
+                },
+                {
+                    "time": "2024-05-02T06:00:00+07:00",
+                    "amount": 300.0,
+                    "unit": "ml",
+                    "type": "Water",
+                    "hydration": 300.0,
+                },
+            ])
+        );
+    }
+
+    #[test]
+    fn only_drinks_within_the_date_range_are_exported() {
+        let first_day = ExportOptions {
+            to: Some(date(1)),
+            ..options(ExportFormat::Csv, false)
+        };
+        let second_day = ExportOptions {
+            from: Some(date(2)),
+            to: Some(date(2)),
+            ..options(ExportFormat::Csv, false)
+        };
+        let later = ExportOptions {
+            from: Some(date(3)),
+            ..options(ExportFormat::Csv, false)
+        };
+
+        // Days are those the drinks were logged on, whatever the current timezone
+        assert_eq!(export(&history(), first_day).lines().count(), 1 + 2);
+        assert_eq!(
+            export(&history(), second_day),
+            "time,amount,unit,type,hydration\n2024-05-02T06:00:00+07:00,300,ml,Water,300\n"
+        );
+        assert_eq!(
+            export(&history(), later),
+            "time,amount,unit,type,hydration\n"
+        );
+    }
+
+    #[test]
+    fn drinks_are_grouped_by_day() {
+        assert_eq!(
+            export(&history(), options(ExportFormat::Csv, true)),
+            "date,amount,unit,hydration\n2024-05-01,500,ml,450\n2024-05-02,300,ml,300\n"
+        );
+
+        let exported: Vec<ExportedDay> =
+            serde_json::from_str(&export(&history(), options(ExportFormat::Json, true))).unwrap();
+        assert_eq!(
+            exported,
+            [
+                ExportedDay {
+                    date: date(1),
+                    amount: 500.0,
+                    unit: AMOUNT_UNIT.to_owned(),
+                    hydration: 450.0,
+                },
+                ExportedDay {
+                    date: date(2),
+                    amount: 300.0,
+                    unit: AMOUNT_UNIT.to_owned(),
+                    hydration: 300.0,
+                },
+            ]
+        );
+    }
+}
@@ -0,0 +1,829 @@
+use std::{fmt, sync::Mutex};
+
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tauri::{AppHandle, Manager};
+use tracing::{instrument, trace, warn};
+
+use crate::{
+    clock::SharedClock,
+    http::REQWEST_CLIENT,
+    storage::{self, AppState},
+    structs::{drink_point::DrinkPoint, google_fit_sync::GoogleFitSync},
+};
+
+const GOOGLE_TOKEN_URL: &str = "https://oauth2.googleapis.com/token";
+const GOOGLE_REVOKE_URL: &str = "https://oauth2.googleapis.com/revoke";
+const GOOGLE_FITNESS_URL: &str = "https://www.googleapis.com/fitness/v1";
+
+/// Client secret of the OAuth client, which Google requires even for desktop apps
+const GOOGLE_CLIENT_SECRET: Option<&str> = option_env!("GOOGLE_CLIENT_SECRET");
+
+const HYDRATION_DATA_TYPE: &str = "com.google.hydration";
+const DATA_STREAM_NAME: &str = "HydrateReminder";
+
+/// Most data points uploaded in a single request
+const MAX_POINTS_PER_UPLOAD: usize = 500;
+
+/// Name the tokens are stored under in the credential store
+const CREDENTIAL_ACCOUNT: &str = "google-fit";
+
+/// Access tokens are refreshed when they expire within this many seconds
+const TOKEN_REFRESH_MARGIN: i64 = 5 * 60;
+
+/// Where the Google APIs live. Can be pointed at a local mock through
+/// `HYDRATE_GOOGLE_TOKEN_URL`, `HYDRATE_GOOGLE_REVOKE_URL` and `HYDRATE_GOOGLE_FITNESS_URL`
+#[derive(Debug, Clone)]
+pub struct Endpoints {
+    pub token: String,
+    pub revoke: String,
+    pub fitness: String,
+}
+
+impl Endpoints {
+    pub fn from_env() -> Self {
+        Self {
+            token: std::env::var("HYDRATE_GOOGLE_TOKEN_URL")
+                .unwrap_or_else(|_| GOOGLE_TOKEN_URL.to_owned()),
+            revoke: std::env::var("HYDRATE_GOOGLE_REVOKE_URL")
+                .unwrap_or_else(|_| GOOGLE_REVOKE_URL.to_owned()),
+            fitness: std::env::var("HYDRATE_GOOGLE_FITNESS_URL")
+                .unwrap_or_else(|_| GOOGLE_FITNESS_URL.to_owned()),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum FitError {
+    /// No tokens are available, the user has to connect their account first
+    NotConnected,
+    /// The access token expired and can't be refreshed, the user has to connect again
+    Expired,
+    Http(reqwest::Error),
+    /// Google answered with an error status
+    Api {
+        status: reqwest::StatusCode,
+        body: String,
+    },
+}
+
+impl fmt::Display for FitError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NotConnected => write!(f, "Google Fit is not connected"),
+            Self::Expired => write!(f, "Google Fit access expired, please connect again"),
+            Self::Http(e) => write!(f, "unable to reach Google: {e}"),
+            Self::Api { status, body } => write!(f, "Google answered {status}: {body}"),
+        }
+    }
+}
+
+impl std::error::Error for FitError {}
+
+impl From<reqwest::Error> for FitError {
+    fn from(e: reqwest::Error) -> Self {
+        Self::Http(e)
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Tokens {
+    pub access_token: String,
+    pub refresh_token: Option<String>,
+    pub expires_at: DateTime<Utc>,
+}
+
+/// OAuth tokens of the connected Google account, if any
+pub struct GoogleFitTokens(pub Mutex<Option<Tokens>>);
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ConnectionStatus {
+    Connected,
+    /// Tokens exist but can't be used or refreshed anymore
+    Expired,
+    Disconnected,
+}
+
+impl ConnectionStatus {
+    pub fn of(tokens: Option<&Tokens>, now: DateTime<Utc>) -> Self {
+        match tokens {
+            None => Self::Disconnected,
+            Some(tokens) if tokens.refresh_token.is_none() && tokens.expires_at <= now => {
+                Self::Expired
+            }
+            Some(_) => Self::Connected,
+        }
+    }
+}
+
+/// Reads the tokens kept in the credential store
+pub fn load_tokens() -> Option<Tokens> {
+    match crate::credentials::load(CREDENTIAL_ACCOUNT) {
+        Ok(tokens) => tokens.and_then(|tokens| serde_json::from_str(&tokens).ok()),
+        Err(e) => {
+            warn!("Unable to load Google Fit tokens: {e}");
+            None
+        }
+    }
+}
+
+/// Keeps `tokens` in memory & in the credential store
+fn store_tokens(app: &AppHandle, tokens: Tokens) {
+    if let Err(e) =
+        crate::credentials::save(CREDENTIAL_ACCOUNT, &serde_json::to_string(&tokens).unwrap())
+    {
+        warn!("Unable to store Google Fit tokens, they will be lost on restart: {e}");
+    }
+
+    *app.state::<GoogleFitTokens>().0.lock().unwrap() = Some(tokens);
+}
+
+fn status_changed(app: &AppHandle) {
+    let now = app.state::<SharedClock>().now();
+    let status = ConnectionStatus::of(
+        app.state::<GoogleFitTokens>().0.lock().unwrap().as_ref(),
+        now,
+    );
+
+    app.emit_all("google-fit-status-changed", status).unwrap();
+}
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    access_token: String,
+    expires_in: i64,
+    refresh_token: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct DataSource {
+    data_stream_id: String,
+    data_stream_name: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct DataSourceList {
+    #[serde(default)]
+    data_source: Vec<DataSource>,
+}
+
+/// Turns error statuses into [`FitError::Api`]
+async fn check_status(response: reqwest::Response) -> Result<reqwest::Response, FitError> {
+    let status = response.status();
+    if status.is_success() {
+        return Ok(response);
+    }
+
+    Err(FitError::Api {
+        status,
+        body: response.text().await.unwrap_or_default(),
+    })
+}
+
+/// Exchanges the authorization code received by the OAuth redirect for tokens
+#[instrument(skip(code, code_verifier))]
+pub async fn exchange_code(
+    endpoints: &Endpoints,
+    client_id: &str,
+    code: &str,
+    redirect_uri: &str,
+    code_verifier: &str,
+    now: DateTime<Utc>,
+) -> Result<Tokens, FitError> {
+    let mut form = vec![
+        ("grant_type", "authorization_code"),
+        ("code", code),
+        ("client_id", client_id),
+        ("redirect_uri", redirect_uri),
+        ("code_verifier", code_verifier),
+    ];
+    if let Some(secret) = GOOGLE_CLIENT_SECRET {
+        form.push(("client_secret", secret));
+    }
+
+    let response = REQWEST_CLIENT
+        .post(&endpoints.token)
+        .form(&form)
+        .send()
+        .await?;
+    let token: TokenResponse = check_status(response).await?.json().await?;
+
+    Ok(Tokens {
+        access_token: token.access_token,
+        refresh_token: token.refresh_token,
+        expires_at: now + Duration::seconds(token.expires_in),
+    })
+}
+
+/// Gets a new access token. Google only sends a new refresh token once in a while, so the
+/// current one is kept otherwise
+#[instrument(skip(refresh_token))]
+pub async fn refresh_tokens(
+    endpoints: &Endpoints,
+    client_id: &str,
+    refresh_token: &str,
+    now: DateTime<Utc>,
+) -> Result<Tokens, FitError> {
+    let mut form = vec![
+        ("grant_type", "refresh_token"),
+        ("refresh_token", refresh_token),
+        ("client_id", client_id),
+    ];
+    if let Some(secret) = GOOGLE_CLIENT_SECRET {
+        form.push(("client_secret", secret));
+    }
+
+    let response = REQWEST_CLIENT
+        .post(&endpoints.token)
+        .form(&form)
+        .send()
+        .await?;
+    let token: TokenResponse = check_status(response).await?.json().await?;
+
+    Ok(Tokens {
+        access_token: token.access_token,
+        refresh_token: token
+            .refresh_token
+            .or_else(|| Some(refresh_token.to_owned())),
+        expires_at: now + Duration::seconds(token.expires_in),
+    })
+}
+
+/// Tokens that are valid for at least [`TOKEN_REFRESH_MARGIN`], refreshing them if needed
+#[instrument(skip(app))]
+pub async fn valid_tokens(app: &AppHandle) -> Result<Tokens, FitError> {
+    let tokens = app.state::<GoogleFitTokens>().0.lock().unwrap().clone();
+    let Some(tokens) = tokens else {
+        return Err(FitError::NotConnected);
+    };
+
+    let now = app.state::<SharedClock>().now();
+    if tokens.expires_at - now > Duration::seconds(TOKEN_REFRESH_MARGIN) {
+        return Ok(tokens);
+    }
+
+    let Some(refresh_token) = tokens.refresh_token.as_deref() else {
+        return Err(FitError::Expired);
+    };
+
+    trace!("Refreshing Google Fit access token");
+    let refreshed = refresh_tokens(
+        &Endpoints::from_env(),
+        &crate::oauth::google_client_id(),
+        refresh_token,
+        now,
+    )
+    .await;
+
+    match refreshed {
+        Ok(refreshed) => {
+            store_tokens(app, refreshed.clone());
+            Ok(refreshed)
+        }
+        // The refresh token was revoked or expired, there's no way back without signing in
+        Err(FitError::Api { status, body }) if status == reqwest::StatusCode::BAD_REQUEST => {
+            warn!("Refresh token was rejected: {body}");
+            store_tokens(
+                app,
+                Tokens {
+                    refresh_token: None,
+                    ..tokens
+                },
+            );
+            status_changed(app);
+            Err(FitError::Expired)
+        }
+        Err(e) => Err(e),
+    }
+}
+
+/// Creates the data source drinks are uploaded to, or finds it if it already exists.
+/// Returns its data stream ID
+#[instrument(skip(tokens))]
+pub async fn ensure_data_source(
+    endpoints: &Endpoints,
+    tokens: &Tokens,
+) -> Result<String, FitError> {
+    let data_sources_url = format!("{}/users/me/dataSources", endpoints.fitness);
+
+    let response = REQWEST_CLIENT
+        .post(&data_sources_url)
+        .bearer_auth(&tokens.access_token)
+        .json(&json!({
+            "dataStreamName": DATA_STREAM_NAME,
+            "type": "raw",
+            "application": { "name": "Hydrate Reminder" },
+            "dataType": {
+                "name": HYDRATION_DATA_TYPE,
+                "field": [{ "name": "volume", "format": "floatPoint" }],
+            },
+        }))
+        .send()
+        .await?;
+
+    // Google refuses to create the same data source twice
+    if response.status() != reqwest::StatusCode::CONFLICT {
+        let data_source: DataSource = check_status(response).await?.json().await?;
+        return Ok(data_source.data_stream_id);
+    }
+
+    trace!("Data source already exists, looking it up");
+    let response = REQWEST_CLIENT
+        .get(&data_sources_url)
+        .query(&[("dataTypeName", HYDRATION_DATA_TYPE)])
+        .bearer_auth(&tokens.access_token)
+        .send()
+        .await?;
+    let data_sources: DataSourceList = check_status(response).await?.json().await?;
+
+    data_sources
+        .data_source
+        .into_iter()
+        .find(|data_source| data_source.data_stream_name.as_deref() == Some(DATA_STREAM_NAME))
+        .map(|data_source| data_source.data_stream_id)
+        .ok_or_else(|| FitError::Api {
+            status: reqwest::StatusCode::CONFLICT,
+            body: "Data source exists but couldn't be found".to_owned(),
+        })
+}
+
+/// Uploads drinks as hydration data points, in liters
+#[instrument(skip(tokens, drinks))]
+pub async fn upload_drinks(
+    endpoints: &Endpoints,
+    tokens: &Tokens,
+    data_stream_id: &str,
+    drinks: &[DrinkPoint],
+) -> Result<(), FitError> {
+    let nanos = |drink: &DrinkPoint| drink.timestamp * 1_000_000_000;
+    let (Some(min_start), Some(max_end)) = (
+        drinks.iter().map(nanos).min(),
+        drinks.iter().map(nanos).max(),
+    ) else {
+        return Ok(());
+    };
+
+    let points: Vec<_> = drinks
+        .iter()
+        .map(|drink| {
+            json!({
+                "dataTypeName": HYDRATION_DATA_TYPE,
+                "startTimeNanos": nanos(drink).to_string(),
+                "endTimeNanos": nanos(drink).to_string(),
+                "value": [{ "fpVal": drink.amount / 1000.0 }],
+            })
+        })
+        .collect();
+
+    let response = REQWEST_CLIENT
+        .patch(format!(
+            "{}/users/me/dataSources/{data_stream_id}/datasets/{min_start}-{max_end}",
+            endpoints.fitness
+        ))
+        .bearer_auth(&tokens.access_token)
+        .json(&json!({
+            "dataSourceId": data_stream_id,
+            "minStartTimeNs": min_start.to_string(),
+            "maxEndTimeNs": max_end.to_string(),
+            "point": points,
+        }))
+        .send()
+        .await?;
+    check_status(response).await?;
+
+    Ok(())
+}
+
+/// Finishes connecting a Google account with the code received by the OAuth redirect,
+/// then queues the whole history for upload, except imported drinks
+#[instrument(skip(app, code, code_verifier))]
+pub async fn connect(
+    app: &AppHandle,
+    client_id: &str,
+    code: &str,
+    redirect_uri: &str,
+    code_verifier: &str,
+) -> Result<(), FitError> {
+    let endpoints = Endpoints::from_env();
+    let now = app.state::<SharedClock>().now();
+
+    let tokens = exchange_code(
+        &endpoints,
+        client_id,
+        code,
+        redirect_uri,
+        code_verifier,
+        now,
+    )
+    .await?;
+    let data_stream_id = ensure_data_source(&endpoints, &tokens).await?;
+    trace!("Uploading drinks to data source {data_stream_id}");
+
+    store_tokens(app, tokens);
+
+    let state = app.state::<AppState>();
+
+    // Queue every drink & drop the lock
+    {
+        let mut app_state = state.0.write().unwrap();
+        app_state.google_fit.data_stream_id = Some(data_stream_id);
+        app_state.google_fit.pending_drinks.clear();
+        app_state.google_fit.next_queued_id = 0;
+    }
+
+    if let Err(e) = storage::save_app_state(&state.0.read().unwrap()) {
+        warn!("Unable to save the Google Fit connection: {e}");
+    }
+
+    app.emit_all("google-fit-connected", ()).unwrap();
+    app.trigger_global("google-fit-connected", None);
+    status_changed(app);
+
+    Ok(())
+}
+
+/// Adds drinks that were added since the last call to the upload queue
+pub fn queue_new_drinks(app: &AppHandle) {
+    let state = app.state::<AppState>();
+
+    // Update the queue & drop the lock
+    {
+        let mut app_state = state.0.write().unwrap();
+        let app_state = &mut *app_state;
+        let queued = app_state.google_fit.pending_drinks.len();
+        if !app_state
+            .google_fit
+            .queue_new_drinks(&app_state.drink_history, app_state.next_drink_id)
+        {
+            return;
+        }
+        trace!(
+            "Queued {} drinks for upload",
+            app_state.google_fit.pending_drinks.len() - queued
+        );
+    }
+
+    if let Err(e) = storage::save_app_state(&state.0.read().unwrap()) {
+        warn!("Unable to save the upload queue: {e}");
+    }
+}
+
+/// Uploads every queued drink. Drinks stay queued if their upload fails
+#[instrument(skip(app))]
+pub async fn upload_queued_drinks(app: &AppHandle) -> Result<(), FitError> {
+    let tokens = valid_tokens(app).await?;
+
+    let state = app.state::<AppState>();
+    let (data_stream_id, drinks) = {
+        let mut app_state = state.0.write().unwrap();
+        let app_state = &mut *app_state;
+        let Some(data_stream_id) = app_state.google_fit.data_stream_id.clone() else {
+            return Err(FitError::NotConnected);
+        };
+
+        (
+            data_stream_id,
+            app_state.google_fit.pending(&app_state.drink_history),
+        )
+    };
+
+    upload_in_chunks(
+        &Endpoints::from_env(),
+        &tokens,
+        &data_stream_id,
+        &drinks,
+        |uploaded| {
+            // Dequeue the uploaded drinks & drop the lock
+            {
+                let mut app_state = state.0.write().unwrap();
+                app_state.google_fit.dequeue(uploaded);
+            }
+
+            if let Err(e) = storage::save_app_state(&state.0.read().unwrap()) {
+                warn!("Unable to save upload progress: {e}");
+            }
+        },
+    )
+    .await
+}
+
+/// Uploads `drinks` [`MAX_POINTS_PER_UPLOAD`] at a time, calling `uploaded` with every chunk
+/// that made it. Stops at the first chunk that fails
+async fn upload_in_chunks(
+    endpoints: &Endpoints,
+    tokens: &Tokens,
+    data_stream_id: &str,
+    drinks: &[DrinkPoint],
+    mut uploaded: impl FnMut(&[DrinkPoint]),
+) -> Result<(), FitError> {
+    for chunk in drinks.chunks(MAX_POINTS_PER_UPLOAD) {
+        upload_drinks(endpoints, tokens, data_stream_id, chunk).await?;
+        trace!("Uploaded {} drinks to Google Fit", chunk.len());
+
+        uploaded(chunk);
+    }
+
+    Ok(())
+}
+
+#[instrument(skip(app))]
+#[tauri::command]
+pub fn get_google_fit_status(app: AppHandle) -> ConnectionStatus {
+    let now = app.state::<SharedClock>().now();
+    ConnectionStatus::of(
+        app.state::<GoogleFitTokens>().0.lock().unwrap().as_ref(),
+        now,
+    )
+}
+
+/// Forgets the connected Google account & stops uploading drinks. Drinks already uploaded
+/// stay in Google Fit
+#[instrument(skip(app))]
+#[tauri::command]
+pub async fn disconnect_google_fit(app: AppHandle) -> Result<(), String> {
+    let tokens = app.state::<GoogleFitTokens>().0.lock().unwrap().take();
+
+    // Revoking is a courtesy, the tokens are forgotten either way
+    if let Some(tokens) = tokens {
+        let token = tokens.refresh_token.unwrap_or(tokens.access_token);
+        let revoked = REQWEST_CLIENT
+            .post(Endpoints::from_env().revoke)
+            .form(&[("token", token)])
+            .send()
+            .await;
+        if let Err(e) = revoked {
+            warn!("Unable to revoke Google Fit tokens: {e}");
+        }
+    }
+
+    crate::credentials::delete(CREDENTIAL_ACCOUNT).map_err(|e| e.to_string())?;
+
+    let state = app.state::<AppState>();
+
+    // Clear the upload queue & drop the lock
+    {
+        let mut app_state = state.0.write().unwrap();
+        app_state.google_fit = GoogleFitSync::default();
+    }
+
+    storage::save_app_state(&state.0.read().unwrap()).map_err(|e| e.to_string())?;
+    status_changed(&app);
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{io::Read, sync::mpsc, thread};
+
+    use super::*;
+    use crate::structs::beverage::WATER_ID;
+
+    /// A request received by [`mock_google`]
+    #[derive(Debug)]
+    struct Received {
+        method: String,
+        url: String,
+        authorization: Option<String>,
+        body: String,
+    }
+
+    /// Answers requests with `responses`, as `(status, body)`, in order. Returns endpoints
+    /// pointing at the server & the requests it received
+    fn mock_google(responses: Vec<(u16, &'static str)>) -> (Endpoints, mpsc::Receiver<Received>) {
+        let server = tiny_http::Server::http("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", server.server_addr().to_ip().unwrap());
+        let (sender, received) = mpsc::channel();
+
+        thread::spawn(move || {
+            for (status, body) in responses {
+                let mut request = server.recv().unwrap();
+                let mut request_body = String::new();
+                request
+                    .as_reader()
+                    .read_to_string(&mut request_body)
+                    .unwrap();
+
+                sender
+                    .send(Received {
+                        method: request.method().to_string(),
+                        url: request.url().to_owned(),
+                        authorization: request
+                            .headers()
+                            .iter()
+                            .find(|header| header.field.equiv("Authorization"))
+                            .map(|header| header.value.to_string()),
+                        body: request_body,
+                    })
+                    .unwrap();
+                request
+                    .respond(tiny_http::Response::from_string(body).with_status_code(status))
+                    .unwrap();
+            }
+        });
+
+        let endpoints = Endpoints {
+            token: format!("{url}/token"),
+            revoke: format!("{url}/revoke"),
+            fitness: format!("{url}/fitness/v1"),
+        };
+        (endpoints, received)
+    }
+
+    fn now() -> DateTime<Utc> {
+        DateTime::from_timestamp(1_715_000_000, 0).unwrap()
+    }
+
+    fn tokens() -> Tokens {
+        Tokens {
+            access_token: "access".to_owned(),
+            refresh_token: Some("refresh".to_owned()),
+            expires_at: now() + Duration::hours(1),
+        }
+    }
+
+    fn drink(id: u64, timestamp: i64) -> DrinkPoint {
+        DrinkPoint {
+            id,
+            ..DrinkPoint::new(
+                250.0,
+                WATER_ID,
+                &DateTime::from_timestamp(timestamp, 0).unwrap(),
+            )
+        }
+    }
+
+    #[tokio::test]
+    async fn exchanges_the_code_for_tokens() {
+        let (endpoints, received) = mock_google(vec![(
+            200,
+            r#"{"access_token":"access","expires_in":3600,"refresh_token":"refresh"}"#,
+        )]);
+
+        let tokens = exchange_code(
+            &endpoints,
+            "client",
+            "the-code",
+            "http://127.0.0.1:1234",
+            "verifier",
+            now(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(tokens.access_token, "access");
+        assert_eq!(tokens.refresh_token.as_deref(), Some("refresh"));
+        assert_eq!(tokens.expires_at, now() + Duration::hours(1));
+
+        let request = received.recv().unwrap();
+        assert_eq!(
+            (request.method.as_str(), request.url.as_str()),
+            ("POST", "/token")
+        );
+        for field in [
+            "grant_type=authorization_code",
+            "code=the-code",
+            "client_id=client",
+            "code_verifier=verifier",
+        ] {
+            assert!(
+                request.body.contains(field),
+                "{field} missing from {request:?}"
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn refreshing_keeps_the_refresh_token_unless_a_new_one_is_sent() {
+        let (endpoints, received) = mock_google(vec![
+            (200, r#"{"access_token":"new-access","expires_in":60}"#),
+            (
+                200,
+                r#"{"access_token":"newer-access","expires_in":60,"refresh_token":"new-refresh"}"#,
+            ),
+            (400, r#"{"error":"invalid_grant"}"#),
+        ]);
+
+        let tokens = refresh_tokens(&endpoints, "client", "refresh", now())
+            .await
+            .unwrap();
+        assert_eq!(tokens.access_token, "new-access");
+        assert_eq!(tokens.refresh_token.as_deref(), Some("refresh"));
+        assert_eq!(tokens.expires_at, now() + Duration::seconds(60));
+        let request = received.recv().unwrap();
+        assert!(request.body.contains("grant_type=refresh_token"));
+        assert!(request.body.contains("refresh_token=refresh"));
+
+        let tokens = refresh_tokens(&endpoints, "client", "refresh", now())
+            .await
+            .unwrap();
+        assert_eq!(tokens.refresh_token.as_deref(), Some("new-refresh"));
+
+        let rejected = refresh_tokens(&endpoints, "client", "refresh", now()).await;
+        assert!(matches!(
+            rejected,
+            Err(FitError::Api { status, .. }) if status == reqwest::StatusCode::BAD_REQUEST
+        ));
+    }
+
+    #[tokio::test]
+    async fn creates_the_data_source() {
+        let (endpoints, received) = mock_google(vec![(200, r#"{"dataStreamId":"raw:stream"}"#)]);
+
+        let id = ensure_data_source(&endpoints, &tokens()).await.unwrap();
+        assert_eq!(id, "raw:stream");
+
+        let request = received.recv().unwrap();
+        assert_eq!(request.method, "POST");
+        assert_eq!(request.url, "/fitness/v1/users/me/dataSources");
+        assert_eq!(request.authorization.as_deref(), Some("Bearer access"));
+        let body: serde_json::Value = serde_json::from_str(&request.body).unwrap();
+        assert_eq!(body["dataStreamName"], DATA_STREAM_NAME);
+        assert_eq!(body["dataType"]["name"], HYDRATION_DATA_TYPE);
+    }
+
+    #[tokio::test]
+    async fn looks_up_the_data_source_if_it_already_exists() {
+        let (endpoints, received) = mock_google(vec![
+            (409, r#"{"error":{"code":409}}"#),
+            (
+                200,
+                r#"{"dataSource":[
+                    {"dataStreamId":"raw:other","dataStreamName":"SomeOtherApp"},
+                    {"dataStreamId":"raw:ours","dataStreamName":"HydrateReminder"}
+                ]}"#,
+            ),
+        ]);
+
+        let id = ensure_data_source(&endpoints, &tokens()).await.unwrap();
+        assert_eq!(id, "raw:ours");
+
+        assert_eq!(received.recv().unwrap().method, "POST");
+        let lookup = received.recv().unwrap();
+        assert_eq!(lookup.method, "GET");
+        assert_eq!(
+            lookup.url,
+            "/fitness/v1/users/me/dataSources?dataTypeName=com.google.hydration"
+        );
+    }
+
+    #[tokio::test]
+    async fn failed_uploads_stay_queued() {
+        let (endpoints, received) = mock_google(vec![(200, "{}"), (500, "Backend error")]);
+        let mut sync = GoogleFitSync {
+            data_stream_id: Some("raw:stream".to_owned()),
+            pending_drinks: vec![],
+            next_queued_id: 1,
+        };
+        let mut history = vec![drink(1, 1_715_000_000), drink(2, 1_715_000_060)];
+
+        assert!(sync.queue_new_drinks(&history, 3));
+        let pending = sync.pending(&history);
+        upload_in_chunks(&endpoints, &tokens(), "raw:stream", &pending, |uploaded| {
+            sync.dequeue(uploaded);
+        })
+        .await
+        .unwrap();
+        assert!(sync.pending_drinks.is_empty());
+
+        let request = received.recv().unwrap();
+        assert_eq!(request.method, "PATCH");
+        assert_eq!(
+            request.url,
+            "/fitness/v1/users/me/dataSources/raw:stream/datasets/\
+             1715000000000000000-1715000060000000000"
+        );
+        let body: serde_json::Value = serde_json::from_str(&request.body).unwrap();
+        assert_eq!(body["point"].as_array().unwrap().len(), 2);
+        assert_eq!(body["point"][0]["value"][0]["fpVal"], 0.25);
+
+        history.push(drink(3, 1_715_000_120));
+        assert!(sync.queue_new_drinks(&history, 4));
+        let pending = sync.pending(&history);
+        let failed = upload_in_chunks(&endpoints, &tokens(), "raw:stream", &pending, |uploaded| {
+            sync.dequeue(uploaded);
+        })
+        .await;
+        assert!(matches!(failed, Err(FitError::Api { .. })));
+        assert_eq!(sync.pending_drinks, [3]);
+    }
+
+    #[test]
+    fn imported_drinks_are_not_queued() {
+        let mut sync = GoogleFitSync {
+            data_stream_id: Some("raw:stream".to_owned()),
+            pending_drinks: vec![],
+            next_queued_id: 1,
+        };
+        let imported = DrinkPoint {
+            imported: true,
+            ..drink(2, 1_715_000_060)
+        };
+
+        assert!(sync.queue_new_drinks(&[drink(1, 1_715_000_000), imported], 3));
+        assert_eq!(sync.pending_drinks, [1]);
+    }
+}
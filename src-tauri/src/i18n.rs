@@ -0,0 +1,121 @@
+use std::{collections::HashMap, sync::LazyLock};
+
+use tracing::warn;
+
+use crate::structs::{
+    beverage::{Beverage, FIRST_CUSTOM_ID},
+    language::Language,
+    tray_preset::TrayPreset,
+};
+
+/// Messages of every language, keyed by message ID. Placeholders are written as `{name}`
+static CATALOGS: LazyLock<HashMap<Language, HashMap<String, String>>> = LazyLock::new(|| {
+    let catalogs = HashMap::from([
+        (Language::English, include_str!("../locales/en.json")),
+        (Language::Indonesian, include_str!("../locales/id.json")),
+    ])
+    .into_iter()
+    .map(|(language, catalog)| {
+        let messages: HashMap<String, String> = serde_json::from_str(catalog)
+            .unwrap_or_else(|e| panic!("Unable to parse the {language:?} message catalog: {e}"));
+        (language, messages)
+    })
+    .collect::<HashMap<_, _>>();
+
+    // Missing messages fall back to English, but should still be noticed
+    let english = &catalogs[&Language::English];
+    for (language, messages) in &catalogs {
+        for key in english.keys().filter(|key| !messages.contains_key(*key)) {
+            warn!("Message {key} is missing from the {language:?} catalog");
+        }
+    }
+
+    catalogs
+});
+
+/// The message with the given ID, with its placeholders filled in from `args`
+pub fn format(language: Language, key: &str, args: &[(&str, &str)]) -> String {
+    let Some(message) = CATALOGS[&language]
+        .get(key)
+        .or_else(|| CATALOGS[&Language::English].get(key))
+    else {
+        warn!("Unknown message {key}");
+        return key.to_owned();
+    };
+
+    args.iter().fold(message.clone(), |message, (name, value)| {
+        message.replace(&format!("{{{name}}}"), value)
+    })
+}
+
+/// The message with the given ID, for messages without placeholders
+pub fn text(language: Language, key: &str) -> String {
+    format(language, key, &[])
+}
+
+/// Label of a tray preset. Default labels are translated, the user's own are shown as written
+pub fn preset_label(language: Language, preset: &TrayPreset) -> String {
+    if preset.label.starts_with("preset-")
+        && CATALOGS[&Language::English].contains_key(&preset.label)
+    {
+        text(language, &preset.label)
+    } else {
+        preset.label.clone()
+    }
+}
+
+/// Name of a beverage. Built-in beverages are translated, the user's own are shown as named
+pub fn beverage_name(language: Language, beverage: &Beverage) -> String {
+    if beverage.id < FIRST_CUSTOM_ID {
+        text(language, &format!("beverage-{}", beverage.id))
+    } else {
+        beverage.name.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeSet;
+
+    use super::*;
+
+    #[test]
+    fn catalogs_have_the_same_keys() {
+        let english: BTreeSet<_> = CATALOGS[&Language::English].keys().collect();
+
+        for language in Language::ALL {
+            let keys: BTreeSet<_> = CATALOGS[&language].keys().collect();
+            assert_eq!(
+                keys, english,
+                "{language:?} catalog keys differ from the English ones"
+            );
+        }
+    }
+
+    #[test]
+    fn fills_in_placeholders() {
+        let label = format(
+            Language::English,
+            "tray-paused-minutes",
+            &[("minutes", "5")],
+        );
+        assert!(label.contains('5') && !label.contains("{minutes}"));
+    }
+
+    #[test]
+    fn translates_default_preset_labels_only() {
+        let mut preset = TrayPreset::defaults(250.0, 50.0).remove(0);
+        assert_eq!(preset_label(Language::Indonesian, &preset), "Minum");
+
+        preset.label = "Big glass".to_owned();
+        assert_eq!(preset_label(Language::Indonesian, &preset), "Big glass");
+    }
+
+    #[test]
+    fn unknown_messages_fall_back_to_the_key() {
+        assert_eq!(
+            text(Language::Indonesian, "no-such-message"),
+            "no-such-message"
+        );
+    }
+}
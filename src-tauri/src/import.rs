@@ -0,0 +1,595 @@
+use std::{collections::HashMap, path::Path};
+
+use chrono::{DateTime, FixedOffset, Local, Utc};
+use quick_xml::events::Event;
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Manager};
+use tracing::{instrument, trace};
+
+use crate::{
+    clock::SharedClock,
+    storage::AppState,
+    structs::{
+        beverage::{Beverage, WATER_ID},
+        drink_point::DrinkPoint,
+        volume_unit::VolumeUnit,
+    },
+};
+
+/// Apple Health record type of water intake
+const APPLE_HEALTH_WATER: &str = "HKQuantityTypeIdentifierDietaryWater";
+
+/// Amount of unreadable rows described in an import preview
+const MAX_REPORTED_ERRORS: usize = 20;
+
+/// Drinks closer than this many milliliters at the same time are considered the same drink
+const DUPLICATE_AMOUNT_TOLERANCE: f64 = 0.5;
+
+/// Which columns or keys of a CSV / JSON file hold each value. Missing fields match the export
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct ColumnMapping {
+    /// ISO-8601 time, `YYYY-MM-DD HH:MM:SS ±HHMM` or unix timestamp in seconds
+    pub time: String,
+    pub amount: String,
+
+    /// Column holding the unit of the amount. `None` uses `default_unit` for every row
+    pub unit: Option<String>,
+    pub default_unit: String,
+
+    /// Column holding the name of the beverage. `None`, or an empty field, imports water
+    #[serde(rename = "type")]
+    pub drink_type: Option<String>,
+
+    /// Separates the fields of a CSV row, usually `,` or `;`
+    pub delimiter: char,
+}
+
+impl Default for ColumnMapping {
+    /// Matches the files written by the export
+    fn default() -> Self {
+        Self {
+            time: "time".to_owned(),
+            amount: "amount".to_owned(),
+            unit: Some("unit".to_owned()),
+            default_unit: "ml".to_owned(),
+            drink_type: Some("type".to_owned()),
+            delimiter: ',',
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(tag = "kind", rename_all = "kebab-case")]
+pub enum ImportSource {
+    Csv {
+        #[serde(default)]
+        mapping: ColumnMapping,
+    },
+    /// An array of objects
+    Json {
+        #[serde(default)]
+        mapping: ColumnMapping,
+    },
+    /// `export.xml` from an Apple Health export
+    AppleHealth,
+    /// Hydration JSON from a Google Fit Takeout, found under `Fit/All Data`
+    GoogleFit,
+}
+
+/// What an import changes or would change
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct ImportPreview {
+    /// Drinks read from the file
+    pub found: usize,
+    /// Drinks that aren't in the history yet
+    pub new: usize,
+    /// Drinks already in the history, or repeated in the file
+    pub duplicates: usize,
+    /// Total amount of the new drinks, in milliliters
+    pub new_amount: f64,
+    /// Unix timestamps of the earliest & latest new drinks
+    pub first: Option<i64>,
+    pub last: Option<i64>,
+    /// Rows that couldn't be read, and why
+    pub errors: Vec<String>,
+    /// Total amount of rows that couldn't be read
+    pub error_count: usize,
+    /// Whether the new drinks were added to the history
+    pub applied: bool,
+}
+
+/// Converts an amount to milliliters
+fn amount_in_ml(amount: f64, unit: &str) -> Option<f64> {
+    let factor = match unit.trim().to_lowercase().as_str() {
+        "ml" | "milliliter" | "milliliters" | "millilitre" | "millilitres" => 1.0,
+        "cl" => 10.0,
+        "dl" => 100.0,
+        "l" | "liter" | "liters" | "litre" | "litres" => VolumeUnit::Liter.milliliters(),
+        "fl_oz_us" | "fl oz" | "fl. oz" | "floz" | "oz" | "us fl oz" => {
+            VolumeUnit::UsFluidOunce.milliliters()
+        }
+        "fl_oz_imp" | "imperial fl oz" | "imp fl oz" => {
+            VolumeUnit::ImperialFluidOunce.milliliters()
+        }
+        "cup_us" | "cup" | "cups" => VolumeUnit::UsCup.milliliters(),
+        _ => return None,
+    };
+
+    Some(amount * factor)
+}
+
+/// Parses a time in any of the formats supported by [`ColumnMapping::time`]
+fn parse_time(text: &str) -> Option<DateTime<FixedOffset>> {
+    let text = text.trim();
+
+    DateTime::parse_from_rfc3339(text)
+        .or_else(|_| DateTime::parse_from_str(text, "%Y-%m-%d %H:%M:%S %z"))
+        .ok()
+        .or_else(|| {
+            let timestamp = text.parse::<i64>().ok()?;
+            Some(with_local_offset(DateTime::from_timestamp(timestamp, 0)?))
+        })
+}
+
+/// Gives a UTC time the offset the system's timezone had at that time
+fn with_local_offset(time: DateTime<Utc>) -> DateTime<FixedOffset> {
+    time.with_timezone(&Local).fixed_offset()
+}
+
+/// The ID of the beverage named `name`, ignoring case
+fn beverage_named(beverages: &[Beverage], name: &str) -> Option<u32> {
+    let name = name.trim();
+    beverages
+        .iter()
+        .find(|beverage| beverage.name.eq_ignore_ascii_case(name))
+        .map(|beverage| beverage.id)
+}
+
+/// A drink read from a file, checked like drinks logged in the app
+fn drink_at(
+    time: DateTime<FixedOffset>,
+    amount: f64,
+    beverage: u32,
+    now: DateTime<Utc>,
+) -> Result<DrinkPoint, String> {
+    let drink = DrinkPoint {
+        imported: true,
+        ..DrinkPoint::new(amount, beverage, &time)
+    };
+    drink.validate(now)?;
+
+    Ok(drink)
+}
+
+fn drink_from_fields(
+    time: Option<&str>,
+    amount: Option<&str>,
+    unit: Option<&str>,
+    drink_type: Option<&str>,
+    mapping: &ColumnMapping,
+    beverages: &[Beverage],
+    now: DateTime<Utc>,
+) -> Result<DrinkPoint, String> {
+    let time = time.ok_or_else(|| format!("Missing \"{}\"", mapping.time))?;
+    let time = parse_time(time).ok_or_else(|| format!("Unreadable time \"{time}\""))?;
+
+    let amount = amount.ok_or_else(|| format!("Missing \"{}\"", mapping.amount))?;
+    let amount: f64 = amount
+        .trim()
+        .parse()
+        .map_err(|_| format!("Unreadable amount \"{amount}\""))?;
+
+    let unit = unit
+        .filter(|unit| !unit.trim().is_empty())
+        .unwrap_or(&mapping.default_unit);
+    let amount = amount_in_ml(amount, unit).ok_or_else(|| format!("Unknown unit \"{unit}\""))?;
+
+    let beverage = match drink_type.filter(|name| !name.trim().is_empty()) {
+        Some(name) => {
+            beverage_named(beverages, name).ok_or_else(|| format!("Unknown beverage \"{name}\""))?
+        }
+        None => WATER_ID,
+    };
+
+    drink_at(time, amount, beverage, now)
+}
+
+/// Splits CSV text into records of fields separated by `delimiter`, handling quoted fields
+fn parse_csv(text: &str, delimiter: char) -> Vec<Vec<String>> {
+    let mut records = vec![];
+    let mut record = vec![];
+    let mut field = String::new();
+    let mut in_quotes = false;
+    let mut chars = text.trim_start_matches('\u{feff}').chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if in_quotes && chars.peek() == Some(&'"') => {
+                field.push('"');
+                chars.next();
+            }
+            '"' => in_quotes = !in_quotes,
+            c if c == delimiter && !in_quotes => record.push(std::mem::take(&mut field)),
+            '\r' if !in_quotes => {}
+            '\n' if !in_quotes => {
+                record.push(std::mem::take(&mut field));
+                records.push(std::mem::take(&mut record));
+            }
+            _ => field.push(c),
+        }
+    }
+
+    if !field.is_empty() || !record.is_empty() {
+        record.push(field);
+        records.push(record);
+    }
+
+    records.retain(|record| record.iter().any(|field| !field.trim().is_empty()));
+    records
+}
+
+fn read_csv(
+    text: &str,
+    mapping: &ColumnMapping,
+    beverages: &[Beverage],
+    now: DateTime<Utc>,
+) -> Result<Vec<Result<DrinkPoint, String>>, String> {
+    let mut records = parse_csv(text, mapping.delimiter).into_iter();
+    let header = records.next().ok_or("The file is empty")?;
+
+    let column = |name: &str| {
+        header
+            .iter()
+            .position(|column| column.trim().eq_ignore_ascii_case(name))
+            .ok_or_else(|| format!("No \"{name}\" column"))
+    };
+    let time_column = column(&mapping.time)?;
+    let amount_column = column(&mapping.amount)?;
+    let unit_column = mapping.unit.as_deref().map(column).transpose()?;
+    let type_column = mapping.drink_type.as_deref().map(column).transpose()?;
+
+    Ok(records
+        .enumerate()
+        .map(|(i, record)| {
+            drink_from_fields(
+                record.get(time_column).map(String::as_str),
+                record.get(amount_column).map(String::as_str),
+                unit_column
+                    .and_then(|column| record.get(column))
+                    .map(String::as_str),
+                type_column
+                    .and_then(|column| record.get(column))
+                    .map(String::as_str),
+                mapping,
+                beverages,
+                now,
+            )
+            // Row numbers count the header as row 1
+            .map_err(|e| format!("Row {}: {e}", i + 2))
+        })
+        .collect())
+}
+
+fn read_json(
+    text: &str,
+    mapping: &ColumnMapping,
+    beverages: &[Beverage],
+    now: DateTime<Utc>,
+) -> Result<Vec<Result<DrinkPoint, String>>, String> {
+    let records: Vec<HashMap<String, serde_json::Value>> =
+        serde_json::from_str(text).map_err(|e| format!("Unreadable JSON: {e}"))?;
+
+    let field = |record: &HashMap<String, serde_json::Value>, key: &str| {
+        record.get(key).and_then(|value| match value {
+            serde_json::Value::String(text) => Some(text.clone()),
+            serde_json::Value::Number(number) => Some(number.to_string()),
+            _ => None,
+        })
+    };
+
+    Ok(records
+        .iter()
+        .enumerate()
+        .map(|(i, record)| {
+            drink_from_fields(
+                field(record, &mapping.time).as_deref(),
+                field(record, &mapping.amount).as_deref(),
+                mapping
+                    .unit
+                    .as_deref()
+                    .and_then(|unit| field(record, unit))
+                    .as_deref(),
+                mapping
+                    .drink_type
+                    .as_deref()
+                    .and_then(|drink_type| field(record, drink_type))
+                    .as_deref(),
+                mapping,
+                beverages,
+                now,
+            )
+            .map_err(|e| format!("Entry {}: {e}", i + 1))
+        })
+        .collect())
+}
+
+/// Reads the water records of an Apple Health `export.xml`, which can be hundreds of megabytes
+fn read_apple_health(
+    path: &Path,
+    now: DateTime<Utc>,
+) -> Result<Vec<Result<DrinkPoint, String>>, String> {
+    let mut reader = quick_xml::Reader::from_file(path).map_err(|e| e.to_string())?;
+    let mut buffer = vec![];
+    let mut drinks = vec![];
+
+    let mapping = ColumnMapping {
+        time: "startDate".to_owned(),
+        amount: "value".to_owned(),
+        drink_type: None,
+        ..ColumnMapping::default()
+    };
+
+    loop {
+        match reader.read_event_into(&mut buffer) {
+            Ok(Event::Start(element) | Event::Empty(element))
+                if element.name().as_ref() == b"Record" =>
+            {
+                let attributes: HashMap<Vec<u8>, String> = element
+                    .attributes()
+                    .filter_map(Result::ok)
+                    .filter_map(|attribute| {
+                        let value = attribute.unescape_value().ok()?.into_owned();
+                        Some((attribute.key.as_ref().to_vec(), value))
+                    })
+                    .collect();
+                let attribute = |name: &str| attributes.get(name.as_bytes()).map(String::as_str);
+
+                if attribute("type") == Some(APPLE_HEALTH_WATER) {
+                    drinks.push(drink_from_fields(
+                        attribute(&mapping.time),
+                        attribute(&mapping.amount),
+                        attribute("unit"),
+                        None,
+                        &mapping,
+                        &[],
+                        now,
+                    ));
+                }
+            }
+            Ok(Event::Eof) => break,
+            Ok(_) => {}
+            Err(e) => return Err(format!("Unreadable XML: {e}")),
+        }
+
+        buffer.clear();
+    }
+
+    Ok(drinks)
+}
+
+#[derive(Deserialize)]
+struct GoogleFitExport {
+    #[serde(rename = "Data Points")]
+    data_points: Vec<GoogleFitDataPoint>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct GoogleFitDataPoint {
+    data_type_name: String,
+    start_time_nanos: i64,
+    fit_value: Vec<GoogleFitValue>,
+}
+
+#[derive(Deserialize)]
+struct GoogleFitValue {
+    value: GoogleFitValueInner,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct GoogleFitValueInner {
+    fp_val: Option<f64>,
+}
+
+/// Reads a Google Fit Takeout hydration file, where amounts are in liters
+fn read_google_fit(
+    text: &str,
+    now: DateTime<Utc>,
+) -> Result<Vec<Result<DrinkPoint, String>>, String> {
+    let export: GoogleFitExport =
+        serde_json::from_str(text).map_err(|e| format!("Unreadable Google Fit export: {e}"))?;
+
+    Ok(export
+        .data_points
+        .iter()
+        .filter(|point| point.data_type_name == "com.google.hydration")
+        .map(|point| {
+            let time =
+                DateTime::from_timestamp(point.start_time_nanos.div_euclid(1_000_000_000), 0)
+                    .ok_or_else(|| format!("Invalid time {}", point.start_time_nanos))?;
+            let liters = point
+                .fit_value
+                .first()
+                .and_then(|value| value.value.fp_val)
+                .ok_or("Missing amount")?;
+
+            // Google Fit doesn't record what was drunk, so everything is imported as water
+            drink_at(with_local_offset(time), liters * 1000.0, WATER_ID, now)
+        })
+        .collect())
+}
+
+fn read_drinks(
+    path: &Path,
+    source: &ImportSource,
+    beverages: &[Beverage],
+    now: DateTime<Utc>,
+) -> Result<Vec<Result<DrinkPoint, String>>, String> {
+    let read_text = || std::fs::read_to_string(path).map_err(|e| e.to_string());
+
+    match source {
+        ImportSource::Csv { mapping } => read_csv(&read_text()?, mapping, beverages, now),
+        ImportSource::Json { mapping } => read_json(&read_text()?, mapping, beverages, now),
+        ImportSource::AppleHealth => read_apple_health(path, now),
+        ImportSource::GoogleFit => read_google_fit(&read_text()?, now),
+    }
+}
+
+fn is_same_drink(a: &DrinkPoint, b: &DrinkPoint) -> bool {
+    a.timestamp == b.timestamp && (a.amount - b.amount).abs() < DUPLICATE_AMOUNT_TOLERANCE
+}
+
+/// Splits imported drinks into the ones missing from `history` and a preview of the import
+pub fn plan_import(
+    history: &[DrinkPoint],
+    imported: Vec<Result<DrinkPoint, String>>,
+) -> (Vec<DrinkPoint>, ImportPreview) {
+    let mut preview = ImportPreview::default();
+    let mut new_drinks: Vec<DrinkPoint> = vec![];
+
+    for drink in imported {
+        let drink = match drink {
+            Ok(drink) => drink,
+            Err(e) => {
+                preview.error_count += 1;
+                if preview.errors.len() < MAX_REPORTED_ERRORS {
+                    preview.errors.push(e);
+                }
+                continue;
+            }
+        };
+        preview.found += 1;
+
+        // The history is sorted by time, so only drinks at the same time have to be compared
+        let start = history.partition_point(|other| other.timestamp < drink.timestamp);
+        let in_history = history[start..]
+            .iter()
+            .take_while(|other| other.timestamp == drink.timestamp)
+            .any(|other| is_same_drink(other, &drink));
+
+        if in_history || new_drinks.iter().any(|other| is_same_drink(other, &drink)) {
+            preview.duplicates += 1;
+        } else {
+            new_drinks.push(drink);
+        }
+    }
+
+    new_drinks.sort_by_key(|drink| drink.timestamp);
+    preview.new = new_drinks.len();
+    preview.new_amount = new_drinks.iter().map(|drink| drink.amount).sum();
+    preview.first = new_drinks.first().map(|drink| drink.timestamp);
+    preview.last = new_drinks.last().map(|drink| drink.timestamp);
+
+    (new_drinks, preview)
+}
+
+/// Imports drinks from `path`. With `dry_run` nothing is changed, only the preview is returned
+#[instrument(skip(app))]
+#[tauri::command]
+pub async fn import_drinks(
+    app: AppHandle,
+    path: String,
+    source: ImportSource,
+    dry_run: bool,
+) -> Result<ImportPreview, String> {
+    let now = app.state::<SharedClock>().now();
+    let state = app.state::<AppState>();
+    let beverages = state.0.read().unwrap().beverages();
+    let imported = read_drinks(Path::new(&path), &source, &beverages, now)?;
+
+    // Plan & merge under the same lock, so the preview matches what's added
+    let preview = {
+        let mut app_state = state.0.write().unwrap();
+        let (new_drinks, mut preview) = plan_import(&app_state.drink_history, imported);
+
+        if !dry_run && !new_drinks.is_empty() {
+            for drink in new_drinks {
+                app_state.add_drink(drink);
+            }
+            preview.applied = true;
+        }
+
+        preview
+    };
+    trace!("Import preview: {preview:?}");
+
+    if preview.applied {
+        crate::drink_history_changed(&app)?;
+    }
+
+    Ok(preview)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::structs::beverage::BUILT_IN_BEVERAGES;
+
+    fn now() -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339("2024-05-01T12:00:00Z")
+            .unwrap()
+            .to_utc()
+    }
+
+    #[test]
+    fn splits_rows_on_the_mapped_delimiter() {
+        let text = "time;amount;unit\n2024-05-01T08:00:00+02:00;250;ml\n";
+        let mapping = ColumnMapping {
+            drink_type: None,
+            delimiter: ';',
+            ..ColumnMapping::default()
+        };
+
+        let drinks = read_csv(text, &mapping, &BUILT_IN_BEVERAGES, now()).unwrap();
+        assert_eq!(drinks.len(), 1);
+        let drink = drinks[0].as_ref().unwrap();
+        assert!((drink.amount - 250.0).abs() < f64::EPSILON);
+        assert_eq!(drink.utc_offset, 2 * 60 * 60);
+        assert!(drink.imported);
+
+        // Other delimiters are part of the field
+        assert_eq!(parse_csv("a;b,c\n", ','), vec![vec!["a;b", "c"]]);
+    }
+
+    #[test]
+    fn rejects_drinks_the_app_would_reject() {
+        let text = "time,amount\n\
+            2024-05-01T11:00:00Z,250\n\
+            2024-05-01T13:00:00Z,250\n\
+            2024-05-01T11:00:00Z,9000\n";
+        let mapping = ColumnMapping {
+            unit: None,
+            drink_type: None,
+            ..ColumnMapping::default()
+        };
+
+        let drinks = read_csv(text, &mapping, &BUILT_IN_BEVERAGES, now()).unwrap();
+        assert!(drinks[0].is_ok());
+        assert_eq!(
+            drinks[1].as_ref().unwrap_err(),
+            "Row 3: Drinks can't be logged in the future"
+        );
+        assert!(drinks[2].is_err());
+    }
+
+    #[test]
+    fn reads_the_beverage_from_the_type_column() {
+        let text = "time,amount,unit,type,hydration\n\
+            2024-05-01T08:00:00Z,250,ml,Coffee,200\n\
+            2024-05-01T09:00:00Z,250,ml,sparkling water,250\n\
+            2024-05-01T10:00:00Z,250,ml,,250\n\
+            2024-05-01T11:00:00Z,250,ml,Lemonade,250\n";
+
+        let drinks = read_csv(text, &ColumnMapping::default(), &BUILT_IN_BEVERAGES, now()).unwrap();
+        let beverage = |i: usize| drinks[i].as_ref().map(|drink| drink.beverage);
+        assert_eq!(beverage(0), Ok(2));
+        assert_eq!(beverage(1), Ok(5));
+        assert_eq!(beverage(2), Ok(WATER_ID));
+        assert_eq!(
+            beverage(3),
+            Err(&"Row 5: Unknown beverage \"Lemonade\"".to_owned())
+        );
+    }
+}
@@ -10,28 +10,46 @@
 // Prevents additional console window on Windows in release, DO NOT REMOVE!!
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
+mod clock;
 mod commands;
+mod credentials;
+mod export;
+mod google_fit;
 mod http;
+mod i18n;
+mod import;
+mod notification;
 mod oauth;
 mod sound;
+mod stats;
 mod storage;
 mod structs;
 mod tasks;
 
-use std::sync::RwLock;
+use std::sync::{Arc, Mutex, RwLock};
 
-use crate::storage::PROJECT_DIR;
+use crate::{
+    clock::{SharedClock, SystemClock},
+    storage::PROJECT_DIR,
+};
 
-use {structs::drink_point::DrinkPoint, tauri::Position};
+use {
+    structs::{
+        beverage::WATER_ID, caffeine::CaffeineSettings, daily_goal::DailyProgress,
+        drink_point::DrinkPoint, language::Language,
+    },
+    tauri::Position,
+};
 
-use rodio::{cpal::traits::HostTrait, OutputStream, Sink};
-use tracing::{instrument, trace, warn};
+use chrono::{DateTime, Timelike, Utc};
+use tracing::{error, instrument, trace, warn};
 use tracing_subscriber::prelude::*;
 
 use sound::drink_audio;
 use storage::AppState;
 use tauri::{
-    AppHandle, CustomMenuItem, Manager, SystemTray, SystemTrayEvent, SystemTrayMenu, WindowBuilder,
+    AppHandle, CustomMenuItem, Manager, SystemTray, SystemTrayEvent, SystemTrayMenu,
+    SystemTraySubmenu, WindowBuilder,
 };
 
 #[cfg(debug_assertions)]
@@ -90,41 +108,436 @@ fn spawn_main_window(app: &AppHandle) {
     }
 }
 
+/// ID of the drink removed by the tray's "Undo last drink" item, while the item is shown
+pub struct UndoableDrink(pub Mutex<Option<u64>>);
+
+/// How long the tray's "Undo last drink" item is shown after a drink
+const UNDO_WINDOW: std::time::Duration = std::time::Duration::from_secs(30);
+
+/// Saves the drink history and lets the frontend & the reminder task know that it changed
+fn drink_history_changed(app: &AppHandle) -> Result<(), String> {
+    let state = app.state::<AppState>();
+    storage::save_app_state(&state.0.read().unwrap()).map_err(|e| e.to_string())?;
+
+    app.emit_all("drink", ()).unwrap();
+    app.trigger_global("drink", None);
+
+    Ok(())
+}
+
 // Learn more about Tauri commands at https://tauri.app/v1/guides/features/command
 #[instrument(skip(app))]
-fn submit_drink(app: &AppHandle, amount: f64) {
+fn submit_drink(app: &AppHandle, amount: f64, beverage: u32) {
+    let now = app.state::<SharedClock>().now();
+    if let Err(e) = record_drink(app, amount, beverage, None, now) {
+        error!("Unable to save drink: {e}");
+    }
+
+    play_drink_sound(app);
+}
+
+/// Adds a drink consumed at `time` to the history. `caffeine` overrides the beverage's own
+#[instrument(skip(app))]
+fn record_drink(
+    app: &AppHandle,
+    amount: f64,
+    beverage: u32,
+    caffeine: Option<f64>,
+    time: DateTime<Utc>,
+) -> Result<DrinkPoint, String> {
     let state = app.state::<AppState>();
+    let now = app.state::<SharedClock>().now();
 
     // Add a new drink point to the history & drop the lock
+    let (drink, daily_goal, caffeine_settings, settings, total_before, total_after) = {
+        let mut app_state = state.0.write().unwrap();
+        let today = app_state.day_settings.date_of(now);
+        let beverages = app_state.beverages();
+        let total_before = stats::total_on_date(
+            &app_state.drink_history,
+            today,
+            &app_state.day_settings,
+            &beverages,
+        );
+
+        let mut drink = DrinkPoint::new(amount, beverage, &app_state.day_settings.localize(time));
+        drink.caffeine = caffeine;
+        drink.id = app_state.add_drink(drink);
+
+        // Backdated drinks from previous days don't count towards today's goal
+        let total_after = stats::total_on_date(
+            &app_state.drink_history,
+            today,
+            &app_state.day_settings,
+            &beverages,
+        );
+
+        (
+            drink,
+            app_state.daily_goal,
+            app_state.caffeine,
+            app_state.settings,
+            total_before,
+            total_after,
+        )
+    };
+
+    drink_history_changed(app)?;
+    offer_undo(app, drink.id);
+
+    // The goal is measured in effective hydration, not raw volume
+    if total_before.hydration < daily_goal.amount && total_after.hydration >= daily_goal.amount {
+        trace!("Daily goal of {}ml reached", daily_goal.amount);
+        let progress = DailyProgress::new(daily_goal.amount, total_after);
+
+        app.emit_all("goal-reached", progress).unwrap();
+        app.trigger_global("goal-reached", None);
+
+        if daily_goal.notify_when_reached {
+            let language = settings.language();
+            let amount = settings.unit.format(total_after.hydration, language);
+            notification::send_message(
+                app,
+                &i18n::text(language, "goal-reached-title"),
+                &i18n::format(
+                    language,
+                    "goal-reached-body",
+                    &[("amount", amount.as_str())],
+                ),
+            );
+        }
+    }
+
+    if caffeine_settings.warnings && total_after.caffeine > total_before.caffeine {
+        warn_about_caffeine(
+            app,
+            &caffeine_settings,
+            total_before,
+            total_after,
+            time,
+            now,
+        );
+    }
+
+    Ok(drink)
+}
+
+/// Warns when a drink of today takes the caffeine past the daily limit, or is too close to bedtime
+fn warn_about_caffeine(
+    app: &AppHandle,
+    settings: &CaffeineSettings,
+    total_before: stats::Total,
+    total_after: stats::Total,
+    time: DateTime<Utc>,
+    now: DateTime<Utc>,
+) {
+    let (local_time, day_start, language) = {
+        let state = app.state::<AppState>();
+        let app_state = state.0.read().unwrap();
+        (
+            app_state.day_settings.localize(time),
+            app_state.day_settings.day_start,
+            app_state.settings.language(),
+        )
+    };
+
+    if total_before.caffeine <= settings.daily_limit && total_after.caffeine > settings.daily_limit
+    {
+        trace!(
+            "Daily caffeine limit of {}mg exceeded",
+            settings.daily_limit
+        );
+        notification::send_message(
+            app,
+            &i18n::text(language, "caffeine-limit-title"),
+            &i18n::format(
+                language,
+                "caffeine-limit-body",
+                &[
+                    (
+                        "amount",
+                        language.format_number(total_after.caffeine, 0).as_str(),
+                    ),
+                    (
+                        "limit",
+                        language.format_number(settings.daily_limit, 1).as_str(),
+                    ),
+                ],
+            ),
+        );
+    }
+
+    // Only drinks that were just had can still keep the user up
+    let minute = u16::try_from(local_time.hour() * 60 + local_time.minute()).unwrap();
+    if now - time < chrono::Duration::hours(1) && settings.is_near_bedtime(minute, day_start) {
+        trace!("Caffeine drank close to bedtime");
+        notification::send_message(
+            app,
+            &i18n::text(language, "caffeine-bedtime-title"),
+            &i18n::text(language, "caffeine-bedtime-body"),
+        );
+    }
+}
+
+/// Shows the tray's "Undo last drink" item for the drink with the given ID
+fn offer_undo(app: &AppHandle, id: u64) {
+    *app.state::<UndoableDrink>().0.lock().unwrap() = Some(id);
+    refresh_tray_menu(app);
+
+    let app = app.clone();
+    tauri::async_runtime::spawn(async move {
+        tokio::time::sleep(UNDO_WINDOW).await;
+
+        // Only hide the item if no other drink has been submitted in the meantime
+        let expired = {
+            let mut undoable_drink = app.state::<UndoableDrink>().0.lock().unwrap();
+            let expired = *undoable_drink == Some(id);
+            if expired {
+                *undoable_drink = None;
+            }
+
+            expired
+        };
+
+        if expired {
+            refresh_tray_menu(&app);
+        }
+    });
+}
+
+/// Removes the drink offered by the tray's "Undo last drink" item. Does nothing once the item
+/// is gone, or if the drink has been deleted in the meantime
+#[instrument(skip(app))]
+fn undo_last_drink(app: &AppHandle) -> Result<Option<DrinkPoint>, String> {
+    let Some(id) = app.state::<UndoableDrink>().0.lock().unwrap().take() else {
+        return Ok(None);
+    };
+    refresh_tray_menu(app);
+
+    let state = app.state::<AppState>();
+    let removed = state.0.write().unwrap().remove_drink(id);
+    trace!("Undid drink {id}: {removed:?}");
+
+    if removed.is_some() {
+        drink_history_changed(app)?;
+    }
+
+    Ok(removed)
+}
+
+/// Snoozes / pauses reminders until the given unix timestamp, or resumes them with `None`
+#[instrument(skip(app))]
+fn set_paused_until(app: &AppHandle, paused_until: Option<i64>) {
+    let state = app.state::<AppState>();
+
+    // Update the pause & drop the lock
     {
         let mut app_state = state.0.write().unwrap();
-        app_state.drink_history.push(DrinkPoint::new(amount));
+        app_state.paused_until = paused_until;
     }
 
     storage::save_app_state(&state.0.read().unwrap()).unwrap();
 
-    app.emit_all("drink", ()).unwrap();
-    app.trigger_global("drink", None);
+    refresh_tray_menu(app);
 
-    play_drink_sound();
+    app.emit_all("pause-changed", paused_until).unwrap();
+    app.trigger_global("pause-changed", None);
 }
 
-#[instrument]
-fn play_drink_sound() {
-    let device_count = rodio::cpal::default_host()
-        .output_devices()
-        .unwrap()
-        .count();
-    trace!("Output device count: {device_count}");
+fn snooze(app: &AppHandle, minutes: u32) {
+    let paused_until = app.state::<SharedClock>().now() + chrono::Duration::minutes(minutes.into());
+    set_paused_until(app, Some(paused_until.timestamp()));
+}
+
+fn pause_until_tomorrow(app: &AppHandle) {
+    let now = app.state::<SharedClock>().now();
+    let timezone = app.state::<AppState>().0.read().unwrap().day_settings.tz();
+
+    let paused_until = match timezone {
+        Some(timezone) => {
+            tasks::timing::start_of_day_after(&now.with_timezone(&timezone)).timestamp()
+        }
+        None => tasks::timing::start_of_day_after(&now.with_timezone(&chrono::Local)).timestamp(),
+    };
+    set_paused_until(app, Some(paused_until));
+}
 
-    if device_count > 0 {
-        tauri::async_runtime::spawn(async move {
-            let (_stream, stream_handle) = OutputStream::try_default().unwrap();
-            let sink = Sink::try_new(&stream_handle).unwrap();
+/// Label of the tray item showing how long reminders are still paused for
+fn pause_status_label(paused_until: i64, now: DateTime<Utc>, language: Language) -> String {
+    let minutes_left = (paused_until - now.timestamp() + 59) / 60;
+
+    match (minutes_left / 60, minutes_left % 60) {
+        (0, minutes) => i18n::format(
+            language,
+            "tray-paused-minutes",
+            &[("minutes", minutes.to_string().as_str())],
+        ),
+        (hours, minutes) => i18n::format(
+            language,
+            "tray-paused-hours",
+            &[
+                ("hours", hours.to_string().as_str()),
+                ("minutes", minutes.to_string().as_str()),
+            ],
+        ),
+    }
+}
 
-            sink.append(drink_audio());
-            sink.sleep_until_end();
+fn build_tray_menu(
+    app_state: &storage::InnerAppState,
+    can_undo: bool,
+    now: DateTime<Utc>,
+) -> SystemTrayMenu {
+    let settings = &app_state.settings;
+    let language = settings.language();
+    let text = |key: &str| i18n::text(language, key);
+
+    // Drinks of anything but water are logged from a submenu, at the "Drink" amount
+    let beverage_menu = app_state
+        .beverages()
+        .iter()
+        .filter(|beverage| beverage.id != WATER_ID)
+        .fold(SystemTrayMenu::new(), |menu, beverage| {
+            menu.add_item(CustomMenuItem::new(
+                format!("beverage-{}", beverage.id),
+                format!(
+                    "{} {} ({})",
+                    beverage.icon,
+                    i18n::beverage_name(language, beverage),
+                    settings.unit.format(settings.drink_amount, language)
+                ),
+            ))
         });
+
+    let tray_menu = app_state
+        .tray_presets
+        .iter()
+        .fold(SystemTrayMenu::new(), |menu, preset| {
+            menu.add_item(CustomMenuItem::new(
+                preset.menu_id(),
+                format!(
+                    "{} {} ({})",
+                    preset.emoji,
+                    i18n::preset_label(language, preset),
+                    settings.unit.format(preset.amount, language)
+                ),
+            ))
+        })
+        .add_submenu(SystemTraySubmenu::new(
+            text("tray-other-drinks"),
+            beverage_menu,
+        ));
+
+    let tray_menu = if can_undo {
+        tray_menu.add_item(CustomMenuItem::new("undo-drink", text("tray-undo")))
+    } else {
+        tray_menu
+    };
+
+    let tray_menu = tray_menu.add_native_item(tauri::SystemTrayMenuItem::Separator);
+
+    let tray_menu = if let Some(paused_until) = app_state.paused_until {
+        tray_menu
+            .add_item(
+                CustomMenuItem::new(
+                    "pause-status",
+                    pause_status_label(paused_until, now, language),
+                )
+                .disabled(),
+            )
+            .add_item(CustomMenuItem::new("resume", text("tray-resume")))
+    } else {
+        tray_menu.add_submenu(SystemTraySubmenu::new(
+            text("tray-snooze"),
+            SystemTrayMenu::new()
+                .add_item(CustomMenuItem::new("snooze-15", text("tray-snooze-15")))
+                .add_item(CustomMenuItem::new("snooze-30", text("tray-snooze-30")))
+                .add_item(CustomMenuItem::new("snooze-60", text("tray-snooze-60")))
+                .add_item(CustomMenuItem::new(
+                    "pause-tomorrow",
+                    text("tray-pause-tomorrow"),
+                )),
+        ))
+    };
+
+    tray_menu
+        .add_native_item(tauri::SystemTrayMenuItem::Separator)
+        .add_item(CustomMenuItem::new("open-settings", text("tray-settings")))
+        .add_item(CustomMenuItem::new("quit", text("tray-quit")))
+}
+
+/// Rebuilds the tray menu from the current app state
+fn refresh_tray_menu(app: &AppHandle) {
+    let can_undo = app.state::<UndoableDrink>().0.lock().unwrap().is_some();
+    let tray_menu = build_tray_menu(
+        &app.state::<AppState>().0.read().unwrap(),
+        can_undo,
+        app.state::<SharedClock>().now(),
+    );
+
+    app.tray_handle()
+        .set_menu(tray_menu)
+        .expect("Unable to update tray menu!");
+}
+
+/// Tells the user that their save file is from a newer version of the app, then quits.
+/// The file is left untouched, so that the newer version can still open it
+fn refuse_saved_data(context: tauri::Context<impl tauri::Assets>) {
+    let language = Language::system();
+    let title = i18n::text(language, "unsupported-version-title");
+    let message = i18n::text(language, "unsupported-version-body");
+
+    // Dialogs need a running event loop, so an app is started just to show it
+    tauri::Builder::default()
+        .setup(move |app| {
+            let app = app.handle();
+            std::thread::spawn(move || {
+                tauri::api::dialog::blocking::message::<tauri::Wry>(None, title, message);
+                app.exit(1);
+            });
+            Ok(())
+        })
+        .run(context)
+        .expect("Error while running tauri application");
+}
+
+/// Tells the user that their save file couldn't be read and what was recovered
+#[instrument(skip(app))]
+fn notify_data_recovered(app: &AppHandle, report: &storage::RecoveryReport) {
+    let language = app
+        .state::<AppState>()
+        .0
+        .read()
+        .unwrap()
+        .settings
+        .language();
+    let date = report
+        .restored_backup
+        .map(|backup| backup.format("%Y-%m-%d").to_string())
+        .unwrap_or_default();
+    let salvaged = report.salvaged_drinks.to_string();
+    let args = [("date", date.as_str()), ("salvaged", salvaged.as_str())];
+
+    let message_key = match (report.restored_backup, report.salvaged_drinks) {
+        (Some(_), 0) => "recovery-backup",
+        (Some(_), _) => "recovery-backup-salvaged",
+        (None, 0) => "recovery-reset",
+        (None, _) => "recovery-salvaged",
+    };
+
+    notification::send_message(
+        app,
+        &i18n::text(language, "recovery-title"),
+        &i18n::format(language, message_key, &args),
+    );
+}
+
+/// Plays the drink sound, if enabled in the settings
+fn play_drink_sound(app: &AppHandle) {
+    let settings = app.state::<AppState>().0.read().unwrap().settings;
+
+    if settings.drink_sound {
+        sound::play(drink_audio, settings.volume);
     }
 }
 
@@ -132,12 +545,50 @@ fn handle_tray_event(app: &AppHandle, event: SystemTrayEvent) {
     match event {
         tauri::SystemTrayEvent::LeftClick { position, .. } => {}
         tauri::SystemTrayEvent::MenuItemClick { id, .. } => match id.as_str() {
-            "drink-full" => submit_drink(app, 200.0),
-            "drink-half" => submit_drink(app, 100.0),
+            "undo-drink" => {
+                if let Err(e) = undo_last_drink(app) {
+                    error!("Unable to save undone drink: {e}");
+                }
+            }
+            "snooze-15" => snooze(app, 15),
+            "snooze-30" => snooze(app, 30),
+            "snooze-60" => snooze(app, 60),
+            "pause-tomorrow" => pause_until_tomorrow(app),
+            "resume" => set_paused_until(app, None),
             "open-settings" => spawn_main_window(app),
 
             "quit" => app.exit(0),
 
+            _ if id.starts_with("preset-") => {
+                let preset = app
+                    .state::<AppState>()
+                    .0
+                    .read()
+                    .unwrap()
+                    .tray_presets
+                    .iter()
+                    .find(|preset| preset.menu_id() == id)
+                    .cloned();
+
+                match preset {
+                    Some(preset) => submit_drink(app, preset.amount, preset.beverage),
+                    None => warn!("Unknown preset menu item clicked: {id}"),
+                }
+            }
+            _ if id.starts_with("beverage-") => {
+                let (amount, beverages) = {
+                    let state = app.state::<AppState>();
+                    let app_state = state.0.read().unwrap();
+                    (app_state.settings.drink_amount, app_state.beverages())
+                };
+
+                match id["beverage-".len()..].parse::<u32>() {
+                    Ok(beverage) if beverages.iter().any(|b| b.id == beverage) => {
+                        submit_drink(app, amount, beverage);
+                    }
+                    _ => warn!("Unknown beverage menu item clicked: {id}"),
+                }
+            }
             _ => {
                 warn!("Unknown menu item clicked: {id}");
             }
@@ -180,15 +631,23 @@ fn main() {
         .unwrap();
     }
 
-    let tray_menu = SystemTrayMenu::new()
-        .add_item(CustomMenuItem::new("drink-full", "🥛 Drink (200ml)"))
-        .add_item(CustomMenuItem::new("drink-half", "💧 Sip (100ml)"))
-        .add_native_item(tauri::SystemTrayMenuItem::Separator)
-        .add_item(CustomMenuItem::new("open-settings", "Settings"))
-        .add_item(CustomMenuItem::new("quit", "Quit"));
+    let context = tauri::generate_context!();
+
+    let (app_state, recovery_report) = match storage::get_saved_data() {
+        Ok(loaded) => loaded,
+        Err(e) => {
+            error!("Unable to open data file {:?}: {e}", storage::data_path());
+            refuse_saved_data(context);
+            return;
+        }
+    };
+
+    trace!("Loaded app state: {app_state:#?}");
+
+    let clock: SharedClock = Arc::new(SystemClock);
 
     let mut tray = SystemTray::new()
-        .with_menu(tray_menu)
+        .with_menu(build_tray_menu(&app_state, false, clock.now()))
         .with_icon(tauri::Icon::Raw(
             include_bytes!("../icons/tray.png").to_vec(),
         ));
@@ -198,12 +657,14 @@ fn main() {
         tray = tray.with_icon_as_template(true);
     }
 
-    let app_state = storage::get_saved_data();
-
-    trace!("Loaded app state: {app_state:#?}");
-
     let mut app = tauri::Builder::default()
         .manage(AppState(RwLock::new(app_state)))
+        .manage(clock)
+        .manage(storage::DataRecovery(recovery_report.clone()))
+        .manage(UndoableDrink(Mutex::new(None)))
+        .manage(google_fit::GoogleFitTokens(Mutex::new(
+            google_fit::load_tokens(),
+        )))
         .system_tray(tray)
         .on_system_tray_event(handle_tray_event)
         .invoke_handler(tauri::generate_handler![
@@ -212,9 +673,44 @@ fn main() {
             commands::list_drinks_group_day,
             commands::get_latest_drink,
             commands::can_send_notification,
+            commands::get_reminder_interval,
+            commands::set_reminder_interval,
+            commands::get_reminder_schedule,
+            commands::set_reminder_schedule,
+            commands::get_paused_until,
+            commands::snooze_reminders,
+            commands::pause_reminders_until_tomorrow,
+            commands::resume_reminders,
+            commands::get_missed_reminder_policy,
+            commands::set_missed_reminder_policy,
+            commands::get_daily_goal,
+            commands::set_daily_goal,
+            commands::get_daily_progress,
+            commands::get_data_recovery,
+            commands::get_day_settings,
+            commands::set_day_settings,
+            commands::get_settings,
+            commands::update_settings,
+            commands::format_volume,
+            commands::convert_volume,
+            commands::list_beverages,
+            commands::set_custom_beverages,
+            commands::get_caffeine_settings,
+            commands::set_caffeine_settings,
+            commands::get_caffeine_status,
+            commands::get_tray_presets,
+            commands::set_tray_presets,
+            commands::delete_drink,
+            commands::edit_drink,
+            commands::undo_last_drink,
+            commands::log_drink,
+            export::export_drinks,
+            import::import_drinks,
+            google_fit::get_google_fit_status,
+            google_fit::disconnect_google_fit,
             oauth::start_oauth_authentication
         ])
-        .build(tauri::generate_context!())
+        .build(context)
         .expect("Error while running tauri application");
 
     #[cfg(target_os = "macos")]
@@ -222,7 +718,34 @@ fn main() {
         app.set_activation_policy(tauri::ActivationPolicy::Accessory);
     }
 
+    #[cfg(target_os = "linux")]
+    match zbus::blocking::Connection::session() {
+        Ok(connection) => {
+            app.manage(notification::linux::SessionBus(connection.clone()));
+
+            let app_handle = app.app_handle();
+            std::thread::spawn(move || {
+                let result = notification::linux::listen_for_actions(&connection, |action| {
+                    notification::handle_action(&app_handle, action);
+                });
+                if let Err(e) = result {
+                    error!("Unable to listen for notification actions: {e}");
+                }
+            });
+        }
+        Err(e) => error!("Unable to connect to the session bus, notifications are disabled: {e}"),
+    }
+
+    if let Some(report) = recovery_report {
+        notify_data_recovered(&app.app_handle(), &report);
+    }
+
+    let app_handle = app.app_handle();
+    app.listen_global("settings-changed", move |_| refresh_tray_menu(&app_handle));
+
     tauri::async_runtime::spawn(tasks::notification::task_manager(app.app_handle()));
+    tauri::async_runtime::spawn(tasks::tray::pause_status_updater(app.app_handle()));
+    tauri::async_runtime::spawn(tasks::google_fit::upload_task(app.app_handle()));
 
     app.run(|_, e| {
         if let tauri::RunEvent::ExitRequested { api, .. } = e {
@@ -0,0 +1,421 @@
+// Desktop notifications for Linux, following the freedesktop.org Desktop Notifications spec
+// Ref: https://specifications.freedesktop.org/notification-spec/latest/
+
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{
+        atomic::{AtomicU32, Ordering},
+        LazyLock, Mutex,
+    },
+};
+
+use tauri::{AppHandle, Manager};
+use tracing::{instrument, trace, warn};
+use zbus::{blocking::Connection, dbus_proxy, zvariant::Value};
+
+use crate::storage::PROJECT_DIR;
+
+const APP_NAME: &str = "Hydrate Reminder";
+
+/// The bundle's icon. AppImages & development builds don't install it into the user's icon
+/// theme, so it's handed to the notification server as a file instead of by name
+const ICON_PNG: &[u8] = include_bytes!("../../icons/desktop-icon.png");
+
+/// `file://` URI of [`ICON_PNG`] written to the cache directory, or an empty string if it
+/// couldn't be written, letting the server pick a default icon
+static ICON_URI: LazyLock<String> = LazyLock::new(|| {
+    let cache_dir = PROJECT_DIR.cache_dir();
+    let path = cache_dir.join("notification-icon.png");
+
+    match std::fs::create_dir_all(cache_dir).and_then(|()| std::fs::write(&path, ICON_PNG)) {
+        Ok(()) => format!("file://{}", path.display()),
+        Err(e) => {
+            warn!("Unable to write the notification icon: {e}");
+            String::new()
+        }
+    }
+});
+
+/// ID of the last drink reminder we sent, replaced by the next one if it's still shown
+static LAST_REMINDER_ID: AtomicU32 = AtomicU32::new(0);
+
+/// IDs of our notifications that are still shown. Action signals are broadcasted for every
+/// app's notifications, so this is used to pick out ours
+static SHOWN_IDS: LazyLock<Mutex<HashSet<u32>>> = LazyLock::new(|| Mutex::new(HashSet::new()));
+
+/// Connection to the user's session bus, shared by sending & listening for actions, as some
+/// notification servers only send action signals to the connection that sent the notification
+pub struct SessionBus(pub Connection);
+
+#[dbus_proxy(
+    interface = "org.freedesktop.Notifications",
+    default_service = "org.freedesktop.Notifications",
+    default_path = "/org/freedesktop/Notifications"
+)]
+trait Notifications {
+    #[allow(clippy::too_many_arguments)]
+    fn notify(
+        &self,
+        app_name: &str,
+        replaces_id: u32,
+        app_icon: &str,
+        summary: &str,
+        body: &str,
+        actions: &[&str],
+        hints: HashMap<&str, Value<'_>>,
+        expire_timeout: i32,
+    ) -> zbus::Result<u32>;
+
+    #[dbus_proxy(signal)]
+    fn action_invoked(&self, id: u32, action_key: &str) -> zbus::Result<()>;
+
+    #[dbus_proxy(signal)]
+    fn notification_closed(&self, id: u32, reason: u32) -> zbus::Result<()>;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Urgency {
+    Low = 0,
+    Normal = 1,
+    Critical = 2,
+}
+
+#[derive(Debug, Clone)]
+pub struct Notification<'a> {
+    pub summary: &'a str,
+    pub body: &'a str,
+    pub urgency: Urgency,
+
+    /// Milliseconds until the notification expires. `-1` lets the server decide, `0` never expires
+    pub expire_timeout: i32,
+
+    /// Buttons to attach as `(action ID, label)`
+    pub actions: &'a [(&'a str, &'a str)],
+
+    /// Whether this is a drink reminder, which replaces the previous reminder if it is still
+    /// shown. Other notifications are shown alongside each other
+    pub is_reminder: bool,
+}
+
+/// Sends a notification to the notification server reachable through `connection`
+///
+/// Returns the ID assigned to the notification by the server
+#[instrument(skip(connection))]
+pub fn send(connection: &Connection, notification: &Notification) -> zbus::Result<u32> {
+    let proxy = NotificationsProxyBlocking::new(connection)?;
+
+    let hints = HashMap::from([
+        ("urgency", Value::U8(notification.urgency as u8)),
+        ("image-path", Value::from(ICON_URI.as_str())),
+    ]);
+    // Actions are sent as a flat list of alternating IDs and labels
+    let actions: Vec<&str> = notification
+        .actions
+        .iter()
+        .flat_map(|(id, label)| [*id, *label])
+        .collect();
+
+    let replaces_id = if notification.is_reminder {
+        LAST_REMINDER_ID.load(Ordering::Relaxed)
+    } else {
+        0
+    };
+
+    let id = proxy.notify(
+        APP_NAME,
+        replaces_id,
+        ICON_URI.as_str(),
+        notification.summary,
+        notification.body,
+        &actions,
+        hints,
+        notification.expire_timeout,
+    )?;
+    trace!("Notification sent with ID {id}");
+    if notification.is_reminder {
+        LAST_REMINDER_ID.store(id, Ordering::Relaxed);
+    }
+    SHOWN_IDS.lock().unwrap().insert(id);
+
+    Ok(id)
+}
+
+/// Sends a notification through the app's [`SessionBus`] connection
+pub fn send_to_session(app: &AppHandle, notification: &Notification) -> zbus::Result<u32> {
+    let Some(session) = app.try_state::<SessionBus>() else {
+        return Err(zbus::Error::Failure(
+            "Not connected to the session bus".to_owned(),
+        ));
+    };
+
+    send(&session.0, notification)
+}
+
+/// Whether `id` is one of our notifications that is still shown
+fn is_shown(id: u32) -> bool {
+    SHOWN_IDS.lock().unwrap().contains(&id)
+}
+
+/// Blocks the current thread, dispatching actions invoked on our notifications to
+/// `on_action`. Closed notifications are forgotten from another thread
+pub fn listen_for_actions(
+    connection: &Connection,
+    mut on_action: impl FnMut(&str),
+) -> zbus::Result<()> {
+    let closed_connection = connection.clone();
+    std::thread::spawn(move || {
+        if let Err(e) = forget_closed_notifications(&closed_connection) {
+            warn!("Unable to listen for closed notifications: {e}");
+        }
+    });
+
+    let proxy = NotificationsProxyBlocking::new(connection)?;
+    for signal in proxy.receive_action_invoked()? {
+        let args = signal.args()?;
+        if !is_shown(args.id) {
+            continue;
+        }
+
+        on_action(args.action_key);
+    }
+
+    Ok(())
+}
+
+/// Blocks the current thread, removing closed notifications from [`SHOWN_IDS`]
+fn forget_closed_notifications(connection: &Connection) -> zbus::Result<()> {
+    let proxy = NotificationsProxyBlocking::new(connection)?;
+    for signal in proxy.receive_notification_closed()? {
+        let args = signal.args()?;
+        trace!("Notification {} closed", args.id);
+
+        SHOWN_IDS.lock().unwrap().remove(&args.id);
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::{BufRead, BufReader},
+        process::{Child, Command, Stdio},
+        sync::mpsc,
+        time::{Duration, Instant},
+    };
+
+    use zbus::{
+        blocking::ConnectionBuilder,
+        names::BusName,
+        zvariant::{DynamicType, OwnedValue},
+    };
+
+    use super::*;
+
+    const PATH: &str = "/org/freedesktop/Notifications";
+    const INTERFACE: &str = "org.freedesktop.Notifications";
+    const TIMEOUT: Duration = Duration::from_secs(5);
+
+    /// A private session bus, stopped when dropped
+    struct Bus {
+        daemon: Child,
+        address: String,
+    }
+
+    impl Bus {
+        fn start() -> Self {
+            let mut daemon = Command::new("dbus-daemon")
+                .args(["--session", "--nofork", "--print-address"])
+                .stdout(Stdio::piped())
+                .spawn()
+                .expect("Unable to start dbus-daemon");
+
+            let mut address = String::new();
+            BufReader::new(daemon.stdout.take().unwrap())
+                .read_line(&mut address)
+                .unwrap();
+
+            Self {
+                daemon,
+                address: address.trim().to_owned(),
+            }
+        }
+
+        fn connect(&self) -> Connection {
+            ConnectionBuilder::address(self.address.as_str())
+                .unwrap()
+                .build()
+                .unwrap()
+        }
+    }
+
+    impl Drop for Bus {
+        fn drop(&mut self) {
+            let _ = self.daemon.kill();
+        }
+    }
+
+    /// Arguments of a `Notify` call received by [`StubServer`]
+    #[derive(Debug)]
+    struct NotifyCall {
+        app_name: String,
+        replaces_id: u32,
+        app_icon: String,
+        summary: String,
+        actions: Vec<String>,
+        hints: HashMap<String, OwnedValue>,
+    }
+
+    /// A notification server that hands out IDs like real ones, reusing replaced IDs
+    struct StubServer {
+        calls: mpsc::Sender<NotifyCall>,
+        last_id: u32,
+    }
+
+    #[zbus::dbus_interface(name = "org.freedesktop.Notifications")]
+    impl StubServer {
+        #[allow(clippy::too_many_arguments)]
+        fn notify(
+            &mut self,
+            app_name: String,
+            replaces_id: u32,
+            app_icon: String,
+            summary: String,
+            _body: String,
+            actions: Vec<String>,
+            hints: HashMap<String, OwnedValue>,
+            _expire_timeout: i32,
+        ) -> u32 {
+            let id = if replaces_id == 0 {
+                self.last_id += 1;
+                self.last_id
+            } else {
+                replaces_id
+            };
+
+            self.calls
+                .send(NotifyCall {
+                    app_name,
+                    replaces_id,
+                    app_icon,
+                    summary,
+                    actions,
+                    hints,
+                })
+                .unwrap();
+            id
+        }
+    }
+
+    fn emit(server: &Connection, signal: &str, body: &(impl serde::Serialize + DynamicType)) {
+        server
+            .emit_signal(None::<BusName>, PATH, INTERFACE, signal, body)
+            .unwrap();
+    }
+
+    fn notification(summary: &str, is_reminder: bool) -> Notification {
+        Notification {
+            summary,
+            body: "",
+            urgency: if is_reminder {
+                Urgency::Critical
+            } else {
+                Urgency::Normal
+            },
+            expire_timeout: 10_000,
+            actions: &[("default", "Open"), ("snooze", "Snooze")],
+            is_reminder,
+        }
+    }
+
+    #[test]
+    #[ignore = "needs dbus-daemon, run with `cargo test -- --ignored`"]
+    fn sends_notifications_and_routes_their_actions() {
+        let bus = Bus::start();
+        let (calls, received_calls) = mpsc::channel();
+        let server = ConnectionBuilder::address(bus.address.as_str())
+            .unwrap()
+            .name(INTERFACE)
+            .unwrap()
+            .serve_at(PATH, StubServer { calls, last_id: 0 })
+            .unwrap()
+            .build()
+            .unwrap();
+        let client = bus.connect();
+
+        // Reminders replace the previous reminder, other notifications never replace anything
+        let reminder = send(&client, &notification("Drink!", true)).unwrap();
+        let goal = send(&client, &notification("Goal reached", false)).unwrap();
+        let caffeine = send(&client, &notification("Caffeine", false)).unwrap();
+        assert_eq!(
+            send(&client, &notification("Drink!", true)).unwrap(),
+            reminder
+        );
+
+        let calls: Vec<NotifyCall> = received_calls.try_iter().collect();
+        let replaced: Vec<(&str, u32)> = calls
+            .iter()
+            .map(|call| (call.summary.as_str(), call.replaces_id))
+            .collect();
+        assert_eq!(
+            replaced,
+            [
+                ("Drink!", 0),
+                ("Goal reached", 0),
+                ("Caffeine", 0),
+                ("Drink!", reminder)
+            ]
+        );
+
+        let call = &calls[0];
+        assert_eq!(call.app_name, APP_NAME);
+        assert!(call.app_icon.starts_with("file://"), "{call:?}");
+        assert_eq!(*call.hints["urgency"], Value::U8(Urgency::Critical as u8));
+        assert_eq!(
+            *call.hints["image-path"],
+            Value::from(call.app_icon.as_str())
+        );
+        assert_eq!(call.actions, ["default", "Open", "snooze", "Snooze"]);
+
+        let (actions, received_actions) = mpsc::channel();
+        let listener = bus.connect();
+        std::thread::spawn(move || {
+            listen_for_actions(&listener, |action| {
+                actions.send(action.to_owned()).unwrap();
+            })
+        });
+
+        // The listener subscribes in the background, so signals are resent until it's ready
+        let started = Instant::now();
+        let action = loop {
+            emit(&server, "ActionInvoked", &(goal, "default"));
+            if let Ok(action) = received_actions.recv_timeout(Duration::from_millis(100)) {
+                break action;
+            }
+            assert!(started.elapsed() < TIMEOUT, "No action was routed");
+        };
+        // Older notifications still route their actions after newer ones were sent
+        assert_eq!(action, "default");
+        while received_actions.try_recv().is_ok() {}
+
+        // Other apps' notifications are ignored
+        emit(&server, "ActionInvoked", &(caffeine + 100, "other-app"));
+        emit(&server, "ActionInvoked", &(reminder, "snooze"));
+        assert_eq!(received_actions.recv_timeout(TIMEOUT).unwrap(), "snooze");
+
+        // So are the ones that were closed
+        // Closed by the user
+        emit(&server, "NotificationClosed", &(goal, 2_u32));
+        let started = Instant::now();
+        while is_shown(goal) {
+            assert!(
+                started.elapsed() < TIMEOUT,
+                "Closed notification wasn't forgotten"
+            );
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        emit(&server, "ActionInvoked", &(goal, "default"));
+        emit(&server, "ActionInvoked", &(caffeine, "caffeine"));
+        assert_eq!(received_actions.recv_timeout(TIMEOUT).unwrap(), "caffeine");
+    }
+}
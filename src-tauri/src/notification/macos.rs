@@ -0,0 +1,86 @@
+use mac_notification_sys::{MainButton, Notification, NotificationResponse};
+use tauri::{AppHandle, Manager};
+use tracing::error;
+
+use super::{handle_action, OPEN_ACTION};
+use crate::{i18n, storage::AppState};
+
+/// Sends a drink reminder with `actions` attached, as `(action ID, label)`
+///
+/// Notification Center blocks until the user interacts with the notification,
+/// so the response is awaited on a separate thread
+pub fn send_reminder(app: &AppHandle, title: &str, message: &str, actions: &[(&str, &str)]) {
+    let language = app
+        .state::<AppState>()
+        .0
+        .read()
+        .unwrap()
+        .settings
+        .language();
+    let drink_menu_label = i18n::text(language, "reminder-drink-menu");
+    let app = app.clone();
+    let title = title.to_owned();
+    let message = message.to_owned();
+    let actions: Vec<(String, String)> = actions
+        .iter()
+        .map(|(action, label)| ((*action).to_owned(), (*label).to_owned()))
+        .collect();
+
+    std::thread::spawn(move || {
+        // The last action is shown as the close button instead of inside the dropdown
+        let Some(((snooze_action, snooze_label), drink_actions)) = actions.split_last() else {
+            return;
+        };
+        let drink_labels: Vec<&str> = drink_actions
+            .iter()
+            .map(|(_, label)| label.as_str())
+            .collect();
+
+        let response = Notification::new()
+            .app_icon("")
+            .title(&title)
+            .message(&message)
+            .main_button(MainButton::DropdownActions(
+                &drink_menu_label,
+                &drink_labels,
+            ))
+            .close_button(snooze_label)
+            .send();
+
+        match response {
+            Ok(NotificationResponse::ActionButton(label)) => {
+                if let Some((action, _)) = actions.iter().find(|(_, l)| *l == label) {
+                    handle_action(&app, action);
+                }
+            }
+            Ok(NotificationResponse::CloseButton(_)) => handle_action(&app, snooze_action),
+            Ok(NotificationResponse::Click) => handle_action(&app, OPEN_ACTION),
+            Ok(_) => {}
+            Err(e) => {
+                error!("Failed to send drink notification: {e}");
+            }
+        }
+    });
+}
+
+/// Sends a notification without any actions, which opens the main window when clicked
+pub fn send_message(app: &AppHandle, title: &str, message: &str) {
+    let app = app.clone();
+    let title = title.to_owned();
+    let message = message.to_owned();
+
+    std::thread::spawn(move || {
+        match Notification::new()
+            .app_icon("")
+            .title(&title)
+            .message(&message)
+            .send()
+        {
+            Ok(NotificationResponse::Click) => handle_action(&app, OPEN_ACTION),
+            Ok(_) => {}
+            Err(e) => {
+                error!("Failed to send notification: {e}");
+            }
+        }
+    });
+}
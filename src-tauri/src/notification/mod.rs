@@ -0,0 +1,108 @@
+use tauri::{AppHandle, Manager};
+#[cfg(any(target_os = "windows", target_os = "linux"))]
+use tracing::error;
+use tracing::{instrument, trace, warn};
+
+use crate::{
+    i18n,
+    storage::AppState,
+    structs::{beverage::WATER_ID, settings::Settings},
+};
+
+#[cfg(target_os = "linux")]
+pub mod linux;
+#[cfg(target_os = "macos")]
+pub mod macos;
+#[cfg(target_os = "windows")]
+pub mod winrt;
+
+/// Action triggered when the body of the notification is clicked
+pub const OPEN_ACTION: &str = "default";
+
+/// Buttons attached to a drink reminder as `(action ID, label)`
+pub fn reminder_actions(settings: &Settings) -> [(&'static str, String); 3] {
+    let language = settings.language();
+    let format_amount = |amount: f64| settings.unit.format(amount, language);
+
+    [
+        (
+            "drink-full",
+            i18n::format(
+                language,
+                "reminder-drink",
+                &[("amount", format_amount(settings.drink_amount).as_str())],
+            ),
+        ),
+        (
+            "drink-half",
+            i18n::format(
+                language,
+                "reminder-sip",
+                &[("amount", format_amount(settings.sip_amount).as_str())],
+            ),
+        ),
+        ("snooze", i18n::text(language, "reminder-snooze")),
+    ]
+}
+
+#[instrument(skip(app))]
+pub fn handle_action(app: &AppHandle, action: &str) {
+    trace!("Notification action invoked: {action}");
+
+    let settings = app.state::<AppState>().0.read().unwrap().settings;
+
+    match action {
+        "drink-full" => crate::submit_drink(app, settings.drink_amount, WATER_ID),
+        "drink-half" => crate::submit_drink(app, settings.sip_amount, WATER_ID),
+        "snooze" => crate::snooze(app, 15),
+        OPEN_ACTION => crate::spawn_main_window(app),
+
+        _ => {
+            warn!("Unknown notification action: {action}");
+        }
+    }
+}
+
+/// Sends a notification without any actions, which opens the main window when clicked
+#[instrument(skip(app))]
+pub fn send_message(app: &AppHandle, title: &str, message: &str) {
+    #[cfg(target_os = "macos")]
+    {
+        macos::send_message(app, title, message);
+    }
+
+    #[cfg(target_os = "windows")]
+    {
+        if let Err(e) =
+            winrt::send_message(app, &app.config().tauri.bundle.identifier, title, message)
+        {
+            error!("Failed to send notification: {e}");
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    {
+        let language = app
+            .state::<AppState>()
+            .0
+            .read()
+            .unwrap()
+            .settings
+            .language();
+        let open_label = i18n::text(language, "notification-open");
+
+        if let Err(e) = linux::send_to_session(
+            app,
+            &linux::Notification {
+                summary: title,
+                body: message,
+                urgency: linux::Urgency::Normal,
+                expire_timeout: 10_000,
+                actions: &[(OPEN_ACTION, open_label.as_str())],
+                is_reminder: false,
+            },
+        ) {
+            error!("Failed to send notification: {e}");
+        }
+    }
+}
@@ -0,0 +1,113 @@
+use std::sync::Mutex;
+
+use tauri::AppHandle;
+use tracing::instrument;
+use windows::{
+    core::{ComInterface, IInspectable, HSTRING},
+    Data::Xml::Dom::XmlDocument,
+    Foundation::TypedEventHandler,
+    UI::Notifications::{ToastActivatedEventArgs, ToastNotification, ToastNotificationManager},
+};
+
+use super::{handle_action, OPEN_ACTION};
+
+/// The toast has to outlive `send_reminder` for its activation handler to keep firing
+static LAST_TOAST: Mutex<Option<ToastNotification>> = Mutex::new(None);
+
+/// Escapes text to be embedded inside the toast XML
+fn escape_xml(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Urgent toasts use the reminder scenario, which keeps them on screen until dismissed
+fn toast_xml(title: &str, message: &str, actions: &[(&str, &str)], is_urgent: bool) -> String {
+    let actions: String = actions
+        .iter()
+        .map(|(id, label)| {
+            format!(
+                r#"<action content="{}" arguments="{id}" activationType="foreground"/>"#,
+                escape_xml(label)
+            )
+        })
+        .collect();
+
+    let scenario = if is_urgent { "reminder" } else { "default" };
+
+    format!(
+        r#"<toast launch="{OPEN_ACTION}" duration="short" scenario="{scenario}">
+            <visual>
+                <binding template="ToastGeneric">
+                    <text>{}</text>
+                    <text>{}</text>
+                </binding>
+            </visual>
+            <audio silent="true"/>
+            <actions>{actions}</actions>
+        </toast>"#,
+        escape_xml(title),
+        escape_xml(message)
+    )
+}
+
+fn show_toast(
+    app: &AppHandle,
+    app_id: &str,
+    title: &str,
+    message: &str,
+    actions: &[(&str, &str)],
+    is_urgent: bool,
+) -> windows::core::Result<()> {
+    let xml = XmlDocument::new()?;
+    xml.LoadXml(&HSTRING::from(toast_xml(
+        title, message, actions, is_urgent,
+    )))?;
+
+    let toast = ToastNotification::CreateToastNotification(&xml)?;
+
+    let app = app.clone();
+    toast.Activated(&TypedEventHandler::<ToastNotification, IInspectable>::new(
+        move |_, args: &Option<IInspectable>| {
+            if let Some(args) = args {
+                let arguments = args.cast::<ToastActivatedEventArgs>()?.Arguments()?;
+                handle_action(&app, &arguments.to_string_lossy());
+            }
+
+            Ok(())
+        },
+    ))?;
+
+    ToastNotificationManager::CreateToastNotifierWithId(&HSTRING::from(app_id))?.Show(&toast)?;
+    *LAST_TOAST.lock().unwrap() = Some(toast);
+
+    Ok(())
+}
+
+/// Shows a drink reminder toast with `actions` attached, as `(action ID, label)`
+///
+/// Activations are delivered in-process while the app is running, which is always the case
+/// for a tray app
+#[instrument(skip(app))]
+pub fn send_reminder(
+    app: &AppHandle,
+    app_id: &str,
+    title: &str,
+    message: &str,
+    actions: &[(&str, &str)],
+    is_urgent: bool,
+) -> windows::core::Result<()> {
+    show_toast(app, app_id, title, message, actions, is_urgent)
+}
+
+/// Shows a toast without any actions, which opens the main window when clicked
+#[instrument(skip(app))]
+pub fn send_message(
+    app: &AppHandle,
+    app_id: &str,
+    title: &str,
+    message: &str,
+) -> windows::core::Result<()> {
+    show_toast(app, app_id, title, message, &[], false)
+}
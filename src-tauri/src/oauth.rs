@@ -1,69 +1,266 @@
+use std::time::{Duration, Instant};
+
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use rand::RngCore;
+use sha2::{Digest, Sha256};
 use tauri::api::shell::open;
 
 use tauri::{AppHandle, Manager, WindowBuilder};
-use tiny_http::{Response, Server};
-use tracing::{debug, trace};
+use tiny_http::{Header, Request, Response, Server};
+use tracing::{debug, error, trace, warn};
+
+use crate::{google_fit, i18n, notification, storage::AppState};
+
+/// Client ID used unless `GOOGLE_CLIENT_ID` is set at build time or `HYDRATE_GOOGLE_CLIENT_ID`
+/// at runtime
+const DEFAULT_GOOGLE_CLIENT_ID: &str =
+    "359154028055-42ip89g4r9m78pgoug1rpropgmbpgfa9.apps.googleusercontent.com";
+const GOOGLE_OAUTH_URL: &str = "https://accounts.google.com/o/oauth2/v2/auth";
+const GOOGLE_FIT_SCOPE: &str = "https://www.googleapis.com/auth/fitness.nutrition.write";
+
+/// How long the user has to finish signing in before the redirect server gives up
+const OAUTH_TIMEOUT: Duration = Duration::from_secs(5 * 60);
+
+pub fn google_client_id() -> String {
+    std::env::var("HYDRATE_GOOGLE_CLIENT_ID").unwrap_or_else(|_| {
+        option_env!("GOOGLE_CLIENT_ID")
+            .unwrap_or(DEFAULT_GOOGLE_CLIENT_ID)
+            .to_owned()
+    })
+}
+
+/// A random URL-safe string, used for the state & the PKCE code verifier
+fn random_token() -> String {
+    let mut bytes = [0u8; 32];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    URL_SAFE_NO_PAD.encode(bytes)
+}
+
+/// One attempt at signing in, valid for a single redirect
+#[derive(Debug)]
+struct OAuthAttempt {
+    client_id: String,
+    redirect_uri: String,
+    /// Sent to Google & expected back unchanged, so that redirects not started by us are rejected
+    state: String,
+    /// PKCE secret, only its hash is sent along with the sign in
+    code_verifier: String,
+}
 
-const GOOGLE_OAUTH_URL: &str = "https://accounts.google.com/o/oauth2/v2/auth?response_type=code&client_id=359154028055-42ip89g4r9m78pgoug1rpropgmbpgfa9.apps.googleusercontent.com&scope=https%3A%2F%2Fwww.googleapis.com%2Fauth%2Ffitness.nutrition.write&prompt=consent&redirect_uri=http%3A%2F%2Flocalhost%3A11132&access_type=online";
+impl OAuthAttempt {
+    fn new(redirect_uri: String) -> Self {
+        Self {
+            client_id: google_client_id(),
+            redirect_uri,
+            state: random_token(),
+            code_verifier: random_token(),
+        }
+    }
+
+    fn authorization_url(&self) -> reqwest::Url {
+        let code_challenge = URL_SAFE_NO_PAD.encode(Sha256::digest(&self.code_verifier));
+
+        reqwest::Url::parse_with_params(
+            GOOGLE_OAUTH_URL,
+            &[
+                ("response_type", "code"),
+                ("client_id", self.client_id.as_str()),
+                ("scope", GOOGLE_FIT_SCOPE),
+                ("prompt", "consent"),
+                ("redirect_uri", self.redirect_uri.as_str()),
+                // Needed to get a refresh token
+                ("access_type", "offline"),
+                ("state", self.state.as_str()),
+                ("code_challenge", code_challenge.as_str()),
+                ("code_challenge_method", "S256"),
+            ],
+        )
+        .unwrap()
+    }
+}
 
 #[tauri::command]
-pub fn start_oauth_authentication(app: AppHandle) {
+pub fn start_oauth_authentication(app: AppHandle) -> Result<(), String> {
     if let Some(window) = app.get_window("oauth") {
         window.set_focus().expect("Unable to focus oauth window!");
-    } else {
-        WindowBuilder::new(&app, "oauth", tauri::WindowUrl::App("oauth".into()))
-            .resizable(false)
-            .closable(true)
-            .build()
-            .expect("Unable to create a new window!");
+        return Ok(());
+    }
+
+    // Let the OS pick a free port, Google accepts any port on loopback redirect URIs
+    let http_server = Server::http("127.0.0.1:0")
+        .map_err(|e| format!("Unable to start the sign in server: {e}"))?;
+    let port = http_server
+        .server_addr()
+        .to_ip()
+        .expect("Sign in server isn't listening on an IP address!")
+        .port();
+    let attempt = OAuthAttempt::new(format!("http://127.0.0.1:{port}"));
+    debug!("HTTP Server now listening on {}", attempt.redirect_uri);
+
+    WindowBuilder::new(&app, "oauth", tauri::WindowUrl::App("oauth".into()))
+        .resizable(false)
+        .closable(true)
+        .build()
+        .expect("Unable to create a new window!");
+
+    open(&app.shell_scope(), attempt.authorization_url(), None).unwrap();
 
-        open(&app.shell_scope(), GOOGLE_OAUTH_URL, None).unwrap();
+    // Spawn OAuth server
+    std::thread::spawn(move || redirect_server(&app, &http_server, attempt));
 
-        // Spawn OAuth server
-        tokio::task::spawn(async move { redirect_server(&app.app_handle()) });
+    Ok(())
+}
+
+fn html_response(status: u16, title: &str, message: &str) -> Response<std::io::Cursor<Vec<u8>>> {
+    let page = format!(
+        r#"<!DOCTYPE html>
+<html>
+  <head>
+    <meta charset="utf-8">
+    <title>{title} - Hydrate Reminder</title>
+    <style>
+      body {{ font-family: system-ui, sans-serif; text-align: center; margin-top: 20vh; }}
+    </style>
+  </head>
+  <body>
+    <h1>{title}</h1>
+    <p>{message}</p>
+  </body>
+</html>"#
+    );
+
+    Response::from_string(page)
+        .with_status_code(status)
+        .with_header(
+            "Content-Type: text/html; charset=utf-8"
+                .parse::<Header>()
+                .unwrap(),
+        )
+}
+
+/// The query parameters of a redirect request, if it's a request for the redirect URI
+fn redirect_params(request: &Request) -> Option<Vec<(String, String)>> {
+    let url = reqwest::Url::parse(&format!("http://127.0.0.1{}", request.url())).ok()?;
+    if url.path() != "/" {
+        return None;
     }
+
+    Some(url.query_pairs().into_owned().collect())
 }
 
-pub fn redirect_server(app: &AppHandle) {
-    let http_server = Server::http("localhost:11132").unwrap();
-    debug!("HTTP Server now listening on {}", http_server.server_addr());
+/// Waits for Google to redirect back with an authorization code, then finishes connecting
+/// Google Fit with it. Gives up after [`OAUTH_TIMEOUT`]
+fn redirect_server(app: &AppHandle, http_server: &Server, attempt: OAuthAttempt) {
+    let deadline = Instant::now() + OAUTH_TIMEOUT;
 
-    for request in http_server.incoming_requests() {
-        let req_url = request.url();
-        trace!("Received request: {req_url}");
+    let code = loop {
+        let request =
+            match http_server.recv_timeout(deadline.saturating_duration_since(Instant::now())) {
+                Ok(Some(request)) => request,
+                Ok(None) => {
+                    warn!("Sign in timed out");
+                    break None;
+                }
+                Err(e) => {
+                    error!("Sign in server failed: {e}");
+                    break None;
+                }
+            };
+        trace!("Received request: {}", request.url());
 
-        if !req_url.starts_with("/?") {
+        let Some(params) = redirect_params(&request) else {
             request
-                .respond(Response::from_string("404 Not Found").with_status_code(404))
+                .respond(html_response(404, "Not found", "There's nothing here."))
                 .ok();
+            continue;
+        };
+        let param = |name: &str| {
+            params
+                .iter()
+                .find(|(key, _)| key == name)
+                .map(|(_, value)| value.as_str())
+        };
 
+        // Anything that doesn't carry our state wasn't started by us
+        if param("state") != Some(attempt.state.as_str()) {
+            warn!("Rejected a sign in redirect with a mismatched state");
+            request
+                .respond(html_response(
+                    400,
+                    "Invalid sign in",
+                    "This sign in wasn't started by Hydrate Reminder. Please try again from the app.",
+                ))
+                .ok();
             continue;
         }
 
-        let qs = querystring::querify(&req_url[2..]);
-        let code = qs.iter().find(|(k, _v)| *k == "code");
-
-        if code.is_none() {
+        if let Some(error) = param("error") {
+            warn!("Sign in was refused: {error}");
             request
-                .respond(Response::from_string("400 Bad Request").with_status_code(400))
+                .respond(html_response(
+                    400,
+                    "Sign in cancelled",
+                    "Google Fit wasn't connected. You may close this window.",
+                ))
                 .ok();
-
-            continue;
+            break None;
         }
 
-        debug!("Received code: {}", code.unwrap().1);
-        app.get_window("oauth").and_then(|w| w.close().ok());
+        let Some(code) = param("code").map(str::to_owned) else {
+            request
+                .respond(html_response(
+                    400,
+                    "Invalid sign in",
+                    "Google didn't send an authorization code. Please try again from the app.",
+                ))
+                .ok();
+            continue;
+        };
 
+        debug!("Received authorization code");
         request
-            .respond(tiny_http::Response::from_string(
-                "Loading... You may close this window",
+            .respond(html_response(
+                200,
+                "Signed in",
+                "Google Fit is being connected. You may close this window.",
             ))
             .ok();
 
-        break;
-    }
+        break Some(code);
+    };
 
     debug!("Shutting down HTTP Server");
     http_server.unblock();
-    std::mem::drop(http_server);
+    app.get_window("oauth").and_then(|w| w.close().ok());
+
+    let Some(code) = code else {
+        return;
+    };
+
+    let app = app.clone();
+    tauri::async_runtime::spawn(async move {
+        if let Err(e) = google_fit::connect(
+            &app,
+            &attempt.client_id,
+            &code,
+            &attempt.redirect_uri,
+            &attempt.code_verifier,
+        )
+        .await
+        {
+            error!("Unable to connect Google Fit: {e}");
+            let language = app
+                .state::<AppState>()
+                .0
+                .read()
+                .unwrap()
+                .settings
+                .language();
+            notification::send_message(
+                &app,
+                &i18n::text(language, "google-fit-error-title"),
+                &i18n::text(language, "google-fit-error-body"),
+            );
+        }
+    });
 }
@@ -1,4 +1,5 @@
-use rodio::Decoder;
+use rodio::{cpal::traits::HostTrait, Decoder, OutputStream, Sink};
+use tracing::{instrument, trace};
 
 const NOTIFICATION_AUDIO: &[u8] = include_bytes!("../assets/notif.mp3");
 const DRINK_AUDIO: &[u8] = include_bytes!("../assets/gulp.mp3");
@@ -12,3 +13,24 @@ pub fn drink_audio() -> Decoder<std::io::Cursor<&'static [u8]>> {
     let audio_buffer = std::io::Cursor::new(DRINK_AUDIO);
     Decoder::new_mp3(audio_buffer).unwrap()
 }
+
+/// Plays a sound at `volume` (0 to 1) in the background, if there is a device to play it on
+#[instrument(skip(audio))]
+pub fn play(audio: fn() -> Decoder<std::io::Cursor<&'static [u8]>>, volume: f32) {
+    let device_count = rodio::cpal::default_host()
+        .output_devices()
+        .unwrap()
+        .count();
+    trace!("Output device count: {device_count}");
+
+    if device_count > 0 {
+        tauri::async_runtime::spawn(async move {
+            let (_stream, stream_handle) = OutputStream::try_default().unwrap();
+            let sink = Sink::try_new(&stream_handle).unwrap();
+
+            sink.set_volume(volume);
+            sink.append(audio());
+            sink.sleep_until_end();
+        });
+    }
+}
@@ -0,0 +1,95 @@
+use std::collections::HashMap;
+
+use chrono::{DateTime, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::structs::{
+    beverage::{self, Beverage},
+    day_settings::DaySettings,
+    drink_point::DrinkPoint,
+};
+
+/// Amount drank over some period, in milliliters
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+pub struct Total {
+    /// Raw volume of everything drank
+    pub volume: f64,
+
+    /// Volume weighted by each beverage's hydration coefficient
+    pub hydration: f64,
+
+    /// Caffeine drank, in milligrams
+    pub caffeine: f64,
+}
+
+impl Total {
+    fn add(&mut self, drink: &DrinkPoint, beverages: &[Beverage]) {
+        self.volume += drink.amount;
+        self.hydration += drink.amount * beverage::find(beverages, drink.beverage).hydration;
+        self.caffeine += caffeine_of(drink, beverages);
+    }
+}
+
+/// Milligrams of caffeine in a drink
+pub fn caffeine_of(drink: &DrinkPoint, beverages: &[Beverage]) -> f64 {
+    drink.caffeine.unwrap_or_else(|| {
+        drink.amount / 100.0 * beverage::find(beverages, drink.beverage).caffeine
+    })
+}
+
+/// Estimated milligrams of caffeine still in the body at `now`, assuming it's absorbed right away
+/// and halves every `half_life` minutes
+pub fn caffeine_in_body(
+    history: &[DrinkPoint],
+    beverages: &[Beverage],
+    half_life: u16,
+    now: DateTime<Utc>,
+) -> f64 {
+    let half_life = f64::from(half_life) * 60.0;
+
+    history
+        .iter()
+        .filter(|drink| drink.timestamp <= now.timestamp())
+        .map(|drink| {
+            let elapsed = (now.timestamp() - drink.timestamp) as f64;
+            caffeine_of(drink, beverages) * 0.5_f64.powf(elapsed / half_life)
+        })
+        .sum()
+}
+
+/// Total amount drank on `date`
+pub fn total_on_date(
+    history: &[DrinkPoint],
+    date: NaiveDate,
+    day_settings: &DaySettings,
+    beverages: &[Beverage],
+) -> Total {
+    let mut total = Total::default();
+
+    for drink in history
+        .iter()
+        .filter(|drink| day_settings.date_of_drink(drink) == date)
+    {
+        total.add(drink, beverages);
+    }
+
+    total
+}
+
+/// Total amount drank per day
+pub fn totals_per_date(
+    history: &[DrinkPoint],
+    day_settings: &DaySettings,
+    beverages: &[Beverage],
+) -> HashMap<NaiveDate, Total> {
+    let mut grouped_drinks: HashMap<NaiveDate, Total> = HashMap::new();
+
+    for drink in history {
+        grouped_drinks
+            .entry(day_settings.date_of_drink(drink))
+            .or_default()
+            .add(drink, beverages);
+    }
+
+    grouped_drinks
+}
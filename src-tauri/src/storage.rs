@@ -1,15 +1,33 @@
 use std::{
+    fmt,
     fs::File,
     io::Write,
-    sync::{LazyLock, RwLock},
+    path::{Path, PathBuf},
+    sync::{LazyLock, Mutex, RwLock},
 };
 
 use capnp::message::{ReaderOptions, TypedReader};
+use chrono::{DateTime, NaiveDateTime, Utc};
 use directories::ProjectDirs;
 use serde::{Deserialize, Serialize};
-use tracing::trace;
+use tracing::{error, trace, warn};
 
-use crate::{app_capnp::app_state, structs::drink_point::DrinkPoint};
+use crate::{
+    app_capnp::{app_state, VolumeUnit as SavedVolumeUnit},
+    structs::{
+        active_window::ActiveWindow,
+        beverage::{self, Beverage},
+        caffeine::CaffeineSettings,
+        daily_goal::DailyGoal,
+        day_settings::DaySettings,
+        drink_point::{DrinkPoint, MAX_DRINK_AMOUNT},
+        google_fit_sync::GoogleFitSync,
+        language::Language,
+        repeat_policy::RepeatPolicy,
+        tray_preset::TrayPreset,
+        volume_unit::VolumeUnit,
+    },
+};
 
 #[cfg(debug_assertions)]
 pub static PROJECT_DIR: LazyLock<ProjectDirs> =
@@ -18,45 +36,384 @@ pub static PROJECT_DIR: LazyLock<ProjectDirs> =
 pub static PROJECT_DIR: LazyLock<ProjectDirs> =
     LazyLock::new(|| ProjectDirs::from("fyi", "angelo", "hydrate-reminder").unwrap());
 
+const DATA_FILE_NAME: &str = "history.bin";
+const BACKUP_DIR_NAME: &str = "backups";
+const BACKUP_TIMESTAMP_FORMAT: &str = "%Y%m%dT%H%M%SZ";
+
+/// Amount of backups of the save file to keep around
+const MAX_BACKUPS: usize = 7;
+/// Minimum time between two backups of the save file
+const BACKUP_INTERVAL: std::time::Duration = std::time::Duration::from_secs(24 * 60 * 60);
+
+/// Why a save file couldn't be loaded
+#[derive(Debug)]
+pub enum LoadError {
+    Io(std::io::Error),
+    Decode(capnp::Error),
+    InvalidText(std::str::Utf8Error),
+    /// The save file was written by a newer version of the app
+    UnsupportedVersion {
+        found: u16,
+        supported: u16,
+    },
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(e) => write!(f, "unable to read save file: {e}"),
+            Self::Decode(e) => write!(f, "unable to decode save file: {e}"),
+            Self::InvalidText(e) => write!(f, "save file contains invalid text: {e}"),
+            Self::UnsupportedVersion { found, supported } => write!(
+                f,
+                "save file has version {found}, but only versions up to {supported} are supported"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for LoadError {}
+
+impl From<std::io::Error> for LoadError {
+    fn from(e: std::io::Error) -> Self {
+        Self::Io(e)
+    }
+}
+
+impl From<capnp::Error> for LoadError {
+    fn from(e: capnp::Error) -> Self {
+        Self::Decode(e)
+    }
+}
+
+impl From<std::str::Utf8Error> for LoadError {
+    fn from(e: std::str::Utf8Error) -> Self {
+        Self::InvalidText(e)
+    }
+}
+
+/// What was done to recover from an unreadable save file
+#[derive(Serialize, Debug, Clone)]
+pub struct RecoveryReport {
+    /// Why the save file couldn't be loaded
+    pub reason: String,
+    /// Where the unreadable save file was moved to
+    pub corrupt_file: Option<PathBuf>,
+    /// When the backup the app state was restored from was made, if any
+    pub restored_backup: Option<DateTime<Utc>>,
+    /// Drinks read from the unreadable save file that were missing from the backup
+    pub salvaged_drinks: usize,
+}
+
+/// How the save file was recovered at startup, if it had to be. Kept for the window to read,
+/// as it may not be open yet when recovery happens
+pub struct DataRecovery(pub Option<RecoveryReport>);
+
+/// Version of the save file structure written by this build of the app
+pub const CURRENT_VERSION: u16 = 3;
+
+/// Upgrade steps run on loaded save files, in order. The step at index `i` upgrades a file
+/// from version `i + 1` to version `i + 2`
+const MIGRATIONS: [fn(&mut InnerAppState); (CURRENT_VERSION - 1) as usize] =
+    [migrate_v1_to_v2, migrate_v2_to_v3];
+
+/// Held while writing the save file, so that concurrent saves don't clobber each other
+static SAVE_LOCK: Mutex<()> = Mutex::new(());
+
 #[derive(Serialize, Deserialize, Debug)]
 pub struct InnerAppState {
     pub version: u16,
     pub has_onboarded: bool,
 
     pub drink_history: Vec<DrinkPoint>,
+
+    /// Seconds to wait after the last drink before sending a reminder
+    pub reminder_interval: u32,
+
+    /// Weekly windows in which reminders may be sent
+    pub reminder_schedule: Vec<ActiveWindow>,
+
+    /// Unix timestamp until which reminders are snoozed / paused
+    pub paused_until: Option<i64>,
+
+    /// How ignored reminders are repeated
+    pub missed_reminder_policy: RepeatPolicy,
+
+    /// The amount of water to drink every day
+    pub daily_goal: DailyGoal,
+
+    /// How drinks are split into days
+    pub day_settings: DaySettings,
+
+    /// ID given to the next drink added to the history
+    pub next_drink_id: u64,
+
+    /// General preferences that don't belong to a more specific section
+    pub settings: Settings,
+
+    /// Progress of uploading drinks to Google Fit
+    pub google_fit: GoogleFitSync,
+
+    /// Beverages added by the user, next to the built-in ones
+    pub custom_beverages: Vec<Beverage>,
+
+    /// How caffeine is tracked & when to warn about it
+    pub caffeine: CaffeineSettings,
+
+    /// Tray menu items that log a drink in one click
+    pub tray_presets: Vec<TrayPreset>,
+}
+
+impl InnerAppState {
+    /// Adds a drink to the history, giving it a new ID. The history is kept in chronological order
+    pub fn add_drink(&mut self, mut drink: DrinkPoint) -> u64 {
+        drink.id = self.next_drink_id;
+        self.next_drink_id += 1;
+
+        let index = self
+            .drink_history
+            .partition_point(|other| other.timestamp <= drink.timestamp);
+        self.drink_history.insert(index, drink);
+
+        drink.id
+    }
+
+    pub fn remove_drink(&mut self, id: u64) -> Option<DrinkPoint> {
+        let index = self.drink_history.iter().position(|drink| drink.id == id)?;
+        Some(self.drink_history.remove(index))
+    }
+
+    /// Built-in beverages followed by the user's own
+    pub fn beverages(&self) -> Vec<Beverage> {
+        beverage::BUILT_IN_BEVERAGES
+            .iter()
+            .chain(&self.custom_beverages)
+            .cloned()
+            .collect()
+    }
 }
 
 pub struct AppState(pub RwLock<InnerAppState>);
 
-const INITIAL_APP_STATE: InnerAppState = InnerAppState {
-    version: 1,
-    has_onboarded: false,
+pub const DEFAULT_REMINDER_INTERVAL: u32 = 60 * 60;
 
-    drink_history: vec![],
-};
+fn initial_app_state() -> InnerAppState {
+    InnerAppState {
+        version: CURRENT_VERSION,
+        has_onboarded: false,
+
+        drink_history: vec![],
 
-fn parse_saved_data(bytes: &[u8]) -> InnerAppState {
-    let saved_data = capnp::serialize_packed::read_message(bytes, ReaderOptions::default())
-        .expect("Unable to serialize saved app data!");
+        reminder_interval: DEFAULT_REMINDER_INTERVAL,
+        reminder_schedule: vec![],
+        paused_until: None,
+        missed_reminder_policy: RepeatPolicy::DEFAULT,
+        daily_goal: DailyGoal::DEFAULT,
+        day_settings: DaySettings {
+            timezone: None,
+            day_start: 0,
+        },
+        next_drink_id: 1,
+        settings: Settings::DEFAULT,
+        google_fit: GoogleFitSync {
+            data_stream_id: None,
+            pending_drinks: vec![],
+            next_queued_id: 0,
+        },
+        custom_beverages: vec![],
+        caffeine: CaffeineSettings::DEFAULT,
+        tray_presets: TrayPreset::defaults(
+            Settings::DEFAULT.drink_amount,
+            Settings::DEFAULT.sip_amount,
+        ),
+    }
+}
+
+fn parse_saved_data(bytes: &[u8]) -> Result<InnerAppState, LoadError> {
+    let saved_data = capnp::serialize_packed::read_message(bytes, ReaderOptions::default())?;
     let saved_data_reader = TypedReader::<_, app_state::Owned>::new(saved_data);
 
-    let saved_data_owned = saved_data_reader.get().unwrap();
+    let saved_data_owned = saved_data_reader.get()?;
+
+    // Newer versions may have changed what the other fields hold, so they aren't read at all
+    let version = saved_data_owned.get_version();
+    if version > CURRENT_VERSION {
+        return Err(LoadError::UnsupportedVersion {
+            found: version,
+            supported: CURRENT_VERSION,
+        });
+    }
+
+    let missed_reminder_policy = saved_data_owned.get_missed_reminder_policy()?;
+    let daily_goal = saved_data_owned.get_daily_goal()?;
+    let day_settings = saved_data_owned.get_day_settings()?;
+    let settings = saved_data_owned.get_settings()?;
+    let google_fit = saved_data_owned.get_google_fit()?;
+    let caffeine = saved_data_owned.get_caffeine()?;
 
     // Don't forget to check if struct exists or not using `has`
-    InnerAppState {
-        version: saved_data_owned.get_version(),
+    let mut state = InnerAppState {
+        version,
         has_onboarded: saved_data_owned.get_has_onboarded(),
 
         drink_history: saved_data_owned
-            .get_drink_history()
-            .unwrap()
+            .get_drink_history()?
             .iter()
             .map(|drink_point| DrinkPoint {
+                id: drink_point.get_id(),
                 timestamp: drink_point.get_timestamp(),
                 amount: drink_point.get_amount(),
+                utc_offset: drink_point.get_utc_offset(),
+                beverage: drink_point.get_beverage(),
+                caffeine: Some(drink_point.get_caffeine()).filter(|mg| *mg >= 0.0),
+                imported: drink_point.get_imported(),
             })
             .collect(),
+
+        reminder_interval: saved_data_owned.get_reminder_interval(),
+        reminder_schedule: saved_data_owned
+            .get_reminder_schedule()?
+            .iter()
+            .map(|window| ActiveWindow {
+                weekday: window.get_weekday(),
+                start: window.get_start(),
+                end: window.get_end(),
+            })
+            .collect(),
+        paused_until: Some(saved_data_owned.get_paused_until()).filter(|t| *t != 0),
+        missed_reminder_policy: RepeatPolicy {
+            interval: missed_reminder_policy.get_interval(),
+            max_repeats: missed_reminder_policy.get_max_repeats(),
+            escalate: missed_reminder_policy.get_escalate(),
+        },
+        daily_goal: DailyGoal {
+            amount: daily_goal.get_amount(),
+            notify_when_reached: daily_goal.get_notify_when_reached(),
+        },
+        day_settings: DaySettings {
+            timezone: Some(day_settings.get_timezone()?.to_str()?.to_owned())
+                .filter(|timezone| !timezone.is_empty()),
+            day_start: day_settings.get_day_start(),
+        },
+        next_drink_id: saved_data_owned.get_next_drink_id(),
+        settings: Settings {
+            drink_amount: settings.get_drink_amount(),
+            sip_amount: settings.get_sip_amount(),
+            drink_sound: settings.get_drink_sound(),
+            reminder_sound: settings.get_reminder_sound(),
+            volume: settings.get_volume(),
+            unit: match settings.get_unit() {
+                Ok(SavedVolumeUnit::Liter) => VolumeUnit::Liter,
+                Ok(SavedVolumeUnit::UsFluidOunce) => VolumeUnit::UsFluidOunce,
+                Ok(SavedVolumeUnit::ImperialFluidOunce) => VolumeUnit::ImperialFluidOunce,
+                Ok(SavedVolumeUnit::UsCup) => VolumeUnit::UsCup,
+                // Units added by a newer version of the app fall back to milliliters
+                Ok(SavedVolumeUnit::Milliliter) | Err(_) => VolumeUnit::Milliliter,
+            },
+            language: Language::from_code(settings.get_language()?.to_str()?),
+        },
+        google_fit: GoogleFitSync {
+            data_stream_id: Some(google_fit.get_data_stream_id()?.to_str()?.to_owned())
+                .filter(|id| !id.is_empty()),
+            pending_drinks: google_fit.get_pending_drinks()?.iter().collect(),
+            next_queued_id: google_fit.get_next_queued_id(),
+        },
+        custom_beverages: saved_data_owned
+            .get_custom_beverages()?
+            .iter()
+            .map(|beverage| -> Result<Beverage, LoadError> {
+                Ok(Beverage {
+                    id: beverage.get_id(),
+                    name: beverage.get_name()?.to_str()?.to_owned(),
+                    icon: beverage.get_icon()?.to_str()?.to_owned(),
+                    color: beverage.get_color()?.to_str()?.to_owned(),
+                    hydration: beverage.get_hydration(),
+                    caffeine: beverage.get_caffeine(),
+                })
+            })
+            .collect::<Result<_, _>>()?,
+        caffeine: CaffeineSettings {
+            half_life: caffeine.get_half_life(),
+            daily_limit: caffeine.get_daily_limit(),
+            bedtime: caffeine.get_bedtime(),
+            bedtime_window: caffeine.get_bedtime_window(),
+            warnings: caffeine.get_warnings(),
+        },
+        tray_presets: saved_data_owned
+            .get_tray_presets()?
+            .iter()
+            .map(|preset| -> Result<TrayPreset, LoadError> {
+                Ok(TrayPreset {
+                    id: preset.get_id(),
+                    label: preset.get_label()?.to_str()?.to_owned(),
+                    emoji: preset.get_emoji()?.to_str()?.to_owned(),
+                    amount: preset.get_amount(),
+                    beverage: preset.get_beverage(),
+                })
+            })
+            .collect::<Result<_, _>>()?,
+    };
+
+    migrate(&mut state);
+
+    Ok(state)
+}
+
+/// Upgrades a freshly loaded app state from an older or the current version to [`CURRENT_VERSION`]
+fn migrate(state: &mut InnerAppState) {
+    // Files written before the version was set are treated as version 1
+    let version = state.version.max(1);
+    for (from, step) in (version..).zip(&MIGRATIONS[usize::from(version - 1)..]) {
+        trace!("Migrating save file from version {from} to {}", from + 1);
+        step(state);
     }
+    state.version = CURRENT_VERSION;
+}
+
+/// Drinks saved before IDs existed are given one
+fn migrate_v1_to_v2(state: &mut InnerAppState) {
+    for drink in state.drink_history.iter_mut().filter(|drink| drink.id == 0) {
+        drink.id = state.next_drink_id;
+        state.next_drink_id += 1;
+    }
+}
+
+/// The tray's "Drink" & "Sip" items become presets, keeping the amounts set for them
+fn migrate_v2_to_v3(state: &mut InnerAppState) {
+    state.tray_presets =
+        TrayPreset::defaults(state.settings.drink_amount, state.settings.sip_amount);
+}
+
+/// Reads whichever drinks can still be read from a save file that failed to load
+fn salvage_drinks(bytes: &[u8]) -> Vec<DrinkPoint> {
+    let Ok(saved_data) = capnp::serialize_packed::read_message(bytes, ReaderOptions::default())
+    else {
+        return vec![];
+    };
+    let saved_data_reader = TypedReader::<_, app_state::Owned>::new(saved_data);
+
+    let Ok(drink_history) = saved_data_reader
+        .get()
+        .and_then(|saved_data| saved_data.get_drink_history())
+    else {
+        return vec![];
+    };
+
+    drink_history
+        .iter()
+        .map(|drink_point| DrinkPoint {
+            id: 0,
+            timestamp: drink_point.get_timestamp(),
+            amount: drink_point.get_amount(),
+            utc_offset: drink_point.get_utc_offset(),
+            beverage: drink_point.get_beverage(),
+            caffeine: Some(drink_point.get_caffeine()).filter(|mg| *mg >= 0.0),
+            imported: drink_point.get_imported(),
+        })
+        .filter(|drink| {
+            drink.timestamp > 0 && drink.amount > 0.0 && drink.amount <= MAX_DRINK_AMOUNT
+        })
+        .collect()
 }
 
 fn serialize_app_state(state: &InnerAppState) -> Vec<u8> {
@@ -65,9 +422,11 @@ fn serialize_app_state(state: &InnerAppState) -> Vec<u8> {
 
     app_state_builder.set_version(state.version);
     app_state_builder.set_has_onboarded(state.has_onboarded);
+    app_state_builder.set_reminder_interval(state.reminder_interval);
+    app_state_builder.set_next_drink_id(state.next_drink_id);
 
     let mut drink_history_builder =
-        app_state_builder.init_drink_history(
+        app_state_builder.reborrow().init_drink_history(
             u32::try_from(state.drink_history.len())
                 .expect("Unable to convert drink history length to u32. Did you change this to string or do you have > 4 billion drink points?")
         );
@@ -78,6 +437,105 @@ fn serialize_app_state(state: &InnerAppState) -> Vec<u8> {
             .get(u32::try_from(i).unwrap());
         drink_point_builder.set_timestamp(drink_point.timestamp);
         drink_point_builder.set_amount(drink_point.amount);
+        drink_point_builder.set_utc_offset(drink_point.utc_offset);
+        drink_point_builder.set_id(drink_point.id);
+        drink_point_builder.set_beverage(drink_point.beverage);
+        drink_point_builder.set_caffeine(drink_point.caffeine.unwrap_or(-1.0));
+        drink_point_builder.set_imported(drink_point.imported);
+    }
+
+    let mut custom_beverages_builder = app_state_builder.reborrow().init_custom_beverages(
+        u32::try_from(state.custom_beverages.len())
+            .expect("Unable to convert custom beverages length to u32"),
+    );
+    for (i, beverage) in state.custom_beverages.iter().enumerate() {
+        let mut beverage_builder = custom_beverages_builder
+            .reborrow()
+            .get(u32::try_from(i).unwrap());
+        beverage_builder.set_id(beverage.id);
+        beverage_builder.set_name(beverage.name.as_str());
+        beverage_builder.set_icon(beverage.icon.as_str());
+        beverage_builder.set_color(beverage.color.as_str());
+        beverage_builder.set_hydration(beverage.hydration);
+        beverage_builder.set_caffeine(beverage.caffeine);
+    }
+
+    let mut tray_presets_builder = app_state_builder.reborrow().init_tray_presets(
+        u32::try_from(state.tray_presets.len())
+            .expect("Unable to convert tray presets length to u32"),
+    );
+    for (i, preset) in state.tray_presets.iter().enumerate() {
+        let mut preset_builder = tray_presets_builder
+            .reborrow()
+            .get(u32::try_from(i).unwrap());
+        preset_builder.set_id(preset.id);
+        preset_builder.set_label(preset.label.as_str());
+        preset_builder.set_emoji(preset.emoji.as_str());
+        preset_builder.set_amount(preset.amount);
+        preset_builder.set_beverage(preset.beverage);
+    }
+
+    let mut caffeine_builder = app_state_builder.reborrow().init_caffeine();
+    caffeine_builder.set_half_life(state.caffeine.half_life);
+    caffeine_builder.set_daily_limit(state.caffeine.daily_limit);
+    caffeine_builder.set_bedtime(state.caffeine.bedtime);
+    caffeine_builder.set_bedtime_window(state.caffeine.bedtime_window);
+    caffeine_builder.set_warnings(state.caffeine.warnings);
+
+    app_state_builder.set_paused_until(state.paused_until.unwrap_or(0));
+
+    let mut missed_reminder_policy_builder =
+        app_state_builder.reborrow().init_missed_reminder_policy();
+    missed_reminder_policy_builder.set_interval(state.missed_reminder_policy.interval);
+    missed_reminder_policy_builder.set_max_repeats(state.missed_reminder_policy.max_repeats);
+    missed_reminder_policy_builder.set_escalate(state.missed_reminder_policy.escalate);
+
+    let mut daily_goal_builder = app_state_builder.reborrow().init_daily_goal();
+    daily_goal_builder.set_amount(state.daily_goal.amount);
+    daily_goal_builder.set_notify_when_reached(state.daily_goal.notify_when_reached);
+
+    let mut day_settings_builder = app_state_builder.reborrow().init_day_settings();
+    day_settings_builder.set_timezone(state.day_settings.timezone.as_deref().unwrap_or(""));
+    day_settings_builder.set_day_start(state.day_settings.day_start);
+
+    let mut settings_builder = app_state_builder.reborrow().init_settings();
+    settings_builder.set_drink_amount(state.settings.drink_amount);
+    settings_builder.set_sip_amount(state.settings.sip_amount);
+    settings_builder.set_drink_sound(state.settings.drink_sound);
+    settings_builder.set_reminder_sound(state.settings.reminder_sound);
+    settings_builder.set_volume(state.settings.volume);
+    settings_builder.set_unit(match state.settings.unit {
+        VolumeUnit::Milliliter => SavedVolumeUnit::Milliliter,
+        VolumeUnit::Liter => SavedVolumeUnit::Liter,
+        VolumeUnit::UsFluidOunce => SavedVolumeUnit::UsFluidOunce,
+        VolumeUnit::ImperialFluidOunce => SavedVolumeUnit::ImperialFluidOunce,
+        VolumeUnit::UsCup => SavedVolumeUnit::UsCup,
+    });
+    settings_builder.set_language(state.settings.language.map_or("", Language::code));
+
+    let mut google_fit_builder = app_state_builder.reborrow().init_google_fit();
+    google_fit_builder.set_data_stream_id(state.google_fit.data_stream_id.as_deref().unwrap_or(""));
+    google_fit_builder.set_next_queued_id(state.google_fit.next_queued_id);
+    let mut pending_drinks_builder = google_fit_builder.init_pending_drinks(
+        u32::try_from(state.google_fit.pending_drinks.len())
+            .expect("Unable to convert pending drinks length to u32"),
+    );
+    for (i, id) in state.google_fit.pending_drinks.iter().enumerate() {
+        pending_drinks_builder.set(u32::try_from(i).unwrap(), *id);
+    }
+
+    let mut reminder_schedule_builder = app_state_builder.init_reminder_schedule(
+        u32::try_from(state.reminder_schedule.len())
+            .expect("Unable to convert reminder schedule length to u32"),
+    );
+
+    for (i, window) in state.reminder_schedule.iter().enumerate() {
+        let mut window_builder = reminder_schedule_builder
+            .reborrow()
+            .get(u32::try_from(i).unwrap());
+        window_builder.set_weekday(window.weekday);
+        window_builder.set_start(window.start);
+        window_builder.set_end(window.end);
     }
 
     let mut serialized_data = Vec::new();
@@ -87,8 +545,24 @@ fn serialize_app_state(state: &InnerAppState) -> Vec<u8> {
     serialized_data
 }
 
-pub fn get_saved_data() -> InnerAppState {
-    let data_path = PROJECT_DIR.data_dir().join("history.bin");
+pub fn data_path() -> PathBuf {
+    PROJECT_DIR.data_dir().join(DATA_FILE_NAME)
+}
+
+pub fn backup_dir() -> PathBuf {
+    PROJECT_DIR.data_dir().join(BACKUP_DIR_NAME)
+}
+
+pub fn load_app_state(path: &Path) -> Result<InnerAppState, LoadError> {
+    parse_saved_data(&std::fs::read(path)?)
+}
+
+/// Loads the saved app state, recovering from backups if the save file can't be read.
+/// Returns what was done to recover, if anything.
+///
+/// Save files from a newer version of the app are left untouched & returned as an error
+pub fn get_saved_data() -> Result<(InnerAppState, Option<RecoveryReport>), LoadError> {
+    let data_path = data_path();
     trace!("Data path: {data_path:?}");
 
     if !PROJECT_DIR
@@ -100,21 +574,479 @@ pub fn get_saved_data() -> InnerAppState {
     };
 
     if data_path.exists() {
-        let binary_data = std::fs::read(data_path).expect("Unable to read data file!");
-
-        return parse_saved_data(&binary_data);
+        return match load_app_state(&data_path) {
+            Ok(state) => Ok((state, None)),
+            // Never touch files from a newer version, they are most likely fine
+            Err(e @ LoadError::UnsupportedVersion { .. }) => Err(e),
+            Err(e) => {
+                error!("Unable to load data file, attempting recovery: {e}");
+                let (state, report) = recover_app_state(&data_path, &backup_dir(), &e, Utc::now());
+                save_app_state(&state).expect("Unable to write recovered data to file!");
+                Ok((state, Some(report)))
+            }
+        };
     }
 
     // If the data file doesn't exist, create it and write the initial data to it
-    save_app_state(&INITIAL_APP_STATE).expect("Unable to write initial data to file!");
-    INITIAL_APP_STATE
+    let state = initial_app_state();
+    save_app_state(&state).expect("Unable to write initial data to file!");
+    Ok((state, None))
 }
 
-pub fn save_app_state(state: &InnerAppState) -> Result<usize, std::io::Error> {
-    let data_path = PROJECT_DIR.data_dir().join("history.bin");
+/// Moves the unreadable save file at `data_path` aside, restores the latest readable backup
+/// from `backup_dir` and adds back any drinks that could still be read from the unreadable file
+fn recover_app_state(
+    data_path: &Path,
+    backup_dir: &Path,
+    load_error: &LoadError,
+    now: DateTime<Utc>,
+) -> (InnerAppState, RecoveryReport) {
+    let salvaged = std::fs::read(data_path)
+        .map(|bytes| salvage_drinks(&bytes))
+        .unwrap_or_default();
+
+    let corrupt_path = data_path.with_file_name(format!(
+        "history-corrupt-{}.bin",
+        now.format(BACKUP_TIMESTAMP_FORMAT)
+    ));
+    let corrupt_file = match std::fs::rename(data_path, &corrupt_path) {
+        Ok(()) => Some(corrupt_path),
+        Err(e) => {
+            warn!("Unable to move unreadable data file aside: {e}");
+            None
+        }
+    };
+
+    let backups = list_backups(backup_dir).unwrap_or_default();
+    let restored = backups
+        .into_iter()
+        .rev()
+        .find_map(|(time, path)| match load_app_state(&path) {
+            Ok(state) => Some((time, state)),
+            Err(e) => {
+                warn!("Unable to load backup {path:?}: {e}");
+                None
+            }
+        });
+    let (restored_backup, mut state) = match restored {
+        Some((time, state)) => (Some(time), state),
+        None => (None, initial_app_state()),
+    };
+
+    let mut salvaged_drinks = 0;
+    for drink in salvaged {
+        let already_restored = state.drink_history.iter().any(|restored| {
+            restored.timestamp == drink.timestamp && restored.amount == drink.amount
+        });
+
+        if !already_restored {
+            state.add_drink(drink);
+            salvaged_drinks += 1;
+        }
+    }
+
+    let report = RecoveryReport {
+        reason: load_error.to_string(),
+        corrupt_file,
+        restored_backup,
+        salvaged_drinks,
+    };
+    warn!("Recovered from unreadable data file: {report:?}");
+
+    (state, report)
+}
 
+pub fn save_app_state(state: &InnerAppState) -> Result<(), std::io::Error> {
     let binary_data = serialize_app_state(state);
-    File::create(data_path)
-        .expect("Unable to create data file!")
-        .write(&binary_data)
+
+    let _lock = SAVE_LOCK.lock().unwrap();
+    let data_path = data_path();
+
+    if let Err(e) = rotate_backups(&data_path, &backup_dir(), Utc::now()) {
+        warn!("Unable to back up data file: {e}");
+    }
+
+    write_atomically(&data_path, &binary_data)
+}
+
+/// Replaces the file at `path` with `data` without ever leaving a partially written file behind.
+/// The data is written to a temporary file & flushed to disk first, then renamed over `path`
+pub fn write_atomically(path: &Path, data: &[u8]) -> Result<(), std::io::Error> {
+    write_atomically_with(path, data, |temp_path| File::create(temp_path))
+}
+
+/// [`write_atomically`], opening the temporary file with `create`, e.g. to set its permissions
+/// before any data is written
+pub fn write_atomically_with(
+    path: &Path,
+    data: &[u8],
+    create: impl FnOnce(&Path) -> Result<File, std::io::Error>,
+) -> Result<(), std::io::Error> {
+    let mut temp_path = path.as_os_str().to_owned();
+    temp_path.push(".tmp");
+    let temp_path = PathBuf::from(temp_path);
+
+    {
+        let mut temp_file = create(&temp_path)?;
+        temp_file.write_all(data)?;
+        temp_file.sync_all()?;
+    }
+
+    std::fs::rename(&temp_path, path)?;
+
+    // Flush the rename itself to disk. Windows doesn't allow opening directories this way
+    #[cfg(unix)]
+    if let Some(parent) = path.parent() {
+        File::open(parent)?.sync_all()?;
+    }
+
+    Ok(())
+}
+
+/// Backups of the save file in `backup_dir`, oldest first
+pub fn list_backups(backup_dir: &Path) -> Result<Vec<(DateTime<Utc>, PathBuf)>, std::io::Error> {
+    let mut backups: Vec<(DateTime<Utc>, PathBuf)> = std::fs::read_dir(backup_dir)?
+        .filter_map(Result::ok)
+        .filter_map(|entry| {
+            let path = entry.path();
+            let timestamp = path
+                .file_name()?
+                .to_str()?
+                .strip_prefix("history-")?
+                .strip_suffix(".bin")?;
+            let time = NaiveDateTime::parse_from_str(timestamp, BACKUP_TIMESTAMP_FORMAT)
+                .ok()?
+                .and_utc();
+
+            Some((time, path))
+        })
+        .collect();
+    backups.sort();
+
+    Ok(backups)
+}
+
+/// Copies the save file at `data_path` into `backup_dir` if the latest backup is older than
+/// [`BACKUP_INTERVAL`], then removes all but the latest [`MAX_BACKUPS`] backups
+fn rotate_backups(
+    data_path: &Path,
+    backup_dir: &Path,
+    now: DateTime<Utc>,
+) -> Result<(), std::io::Error> {
+    if !data_path.exists() {
+        return Ok(());
+    }
+
+    std::fs::create_dir_all(backup_dir)?;
+    let mut backups = list_backups(backup_dir)?;
+
+    if backups.last().is_some_and(|(time, _)| {
+        (now - *time)
+            .to_std()
+            .is_ok_and(|age| age < BACKUP_INTERVAL)
+    }) {
+        return Ok(());
+    }
+
+    let backup_path = backup_dir.join(format!(
+        "history-{}.bin",
+        now.format(BACKUP_TIMESTAMP_FORMAT)
+    ));
+    trace!("Backing up data file to {backup_path:?}");
+    write_atomically(&backup_path, &std::fs::read(data_path)?)?;
+    backups.push((now, backup_path));
+
+    for (_, path) in &backups[..backups.len().saturating_sub(MAX_BACKUPS)] {
+        trace!("Removing old backup {path:?}");
+        std::fs::remove_file(path)?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A new empty directory for a test to write files to
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!(
+            "hydrate-reminder-test-{name}-{}",
+            std::process::id()
+        ));
+        if dir.exists() {
+            std::fs::remove_dir_all(&dir).unwrap();
+        }
+        std::fs::create_dir_all(&dir).unwrap();
+
+        dir
+    }
+
+    #[test]
+    fn leftover_temp_files_dont_affect_the_data_file() {
+        let dir = test_dir("leftover-temp");
+        let data_path = dir.join(DATA_FILE_NAME);
+        let temp_path = dir.join(format!("{DATA_FILE_NAME}.tmp"));
+
+        write_atomically(&data_path, b"saved").unwrap();
+
+        // A save that was interrupted before its rename
+        std::fs::write(&temp_path, b"half-wri").unwrap();
+        assert_eq!(std::fs::read(&data_path).unwrap(), b"saved");
+
+        // The next save replaces the leftover instead of appending to it
+        write_atomically(&data_path, b"saved again").unwrap();
+        assert_eq!(std::fs::read(&data_path).unwrap(), b"saved again");
+        assert!(!temp_path.exists());
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn backups_are_rotated_and_capped() {
+        let dir = test_dir("rotate-backups");
+        let data_path = dir.join(DATA_FILE_NAME);
+        let backup_dir = dir.join(BACKUP_DIR_NAME);
+        let start = DateTime::from_timestamp(1_714_975_200, 0).unwrap();
+
+        // Nothing to back up yet
+        rotate_backups(&data_path, &backup_dir, start).unwrap();
+        assert!(!backup_dir.exists());
+
+        for day in 0..10 {
+            let now = start + chrono::Duration::days(day);
+            std::fs::write(&data_path, day.to_string()).unwrap();
+            rotate_backups(&data_path, &backup_dir, now).unwrap();
+
+            // Saves within a day of the last backup aren't backed up again
+            rotate_backups(&data_path, &backup_dir, now + chrono::Duration::hours(1)).unwrap();
+        }
+
+        let backups = list_backups(&backup_dir).unwrap();
+        assert_eq!(backups.len(), MAX_BACKUPS);
+        assert_eq!(backups[0].0, start + chrono::Duration::days(3));
+        assert_eq!(std::fs::read_to_string(&backups[0].1).unwrap(), "3");
+        assert_eq!(std::fs::read_to_string(&backups[6].1).unwrap(), "9");
+        assert_eq!(std::fs::read_dir(&backup_dir).unwrap().count(), MAX_BACKUPS);
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    /// Save files in the shape each version of the app wrote them
+    const FIXTURES: [(u16, &[u8]); 3] = [
+        (1, include_bytes!("../tests/fixtures/history-v1.bin")),
+        (2, include_bytes!("../tests/fixtures/history-v2.bin")),
+        (3, include_bytes!("../tests/fixtures/history-v3.bin")),
+    ];
+
+    #[test]
+    fn every_version_survives_a_round_trip() {
+        for (version, bytes) in FIXTURES {
+            let state = parse_saved_data(bytes)
+                .unwrap_or_else(|e| panic!("Unable to load version {version}: {e}"));
+            assert_eq!(state.version, CURRENT_VERSION);
+
+            let reloaded = parse_saved_data(&serialize_app_state(&state)).unwrap();
+            assert_eq!(
+                serde_json::to_value(&reloaded).unwrap(),
+                serde_json::to_value(&state).unwrap(),
+                "Version {version} changed after saving it again"
+            );
+        }
+    }
+
+    #[test]
+    fn version_1_drinks_are_given_ids() {
+        let state = parse_saved_data(FIXTURES[0].1).unwrap();
+
+        let drinks: Vec<_> = state
+            .drink_history
+            .iter()
+            .map(|drink| (drink.id, drink.timestamp))
+            .collect();
+        assert_eq!(
+            drinks,
+            [(1, 1_704_096_000), (2, 1_704_103_200), (3, 1_704_110_400)]
+        );
+        assert_eq!(state.next_drink_id, 4);
+        assert!(state.has_onboarded);
+        assert_eq!(state.reminder_interval, DEFAULT_REMINDER_INTERVAL);
+    }
+
+    #[test]
+    fn version_2_tray_items_become_presets() {
+        let state = parse_saved_data(FIXTURES[1].1).unwrap();
+
+        assert_eq!(state.tray_presets, TrayPreset::defaults(300.0, 75.0));
+        assert_eq!(state.settings.unit, VolumeUnit::Milliliter);
+        assert_eq!(state.settings.language, None);
+
+        // Everything else is kept as is
+        let ids: Vec<_> = state.drink_history.iter().map(|drink| drink.id).collect();
+        assert_eq!(ids, [1, 2, 5]);
+        assert_eq!(state.drink_history[1].caffeine, Some(95.0));
+        assert_eq!(state.next_drink_id, 6);
+        assert_eq!(state.day_settings.timezone.as_deref(), Some("Asia/Jakarta"));
+        assert_eq!(state.custom_beverages[0].name, "Kombucha");
+        assert_eq!(state.caffeine.half_life, 240);
+    }
+
+    #[test]
+    fn version_3_loads_as_saved() {
+        let state = parse_saved_data(FIXTURES[2].1).unwrap();
+
+        assert_eq!(state.settings.unit, VolumeUnit::UsFluidOunce);
+        assert_eq!(state.settings.language, Some(Language::Indonesian));
+        assert_eq!(state.tray_presets.len(), 2);
+        assert_eq!(state.tray_presets[1].label, "Morning coffee");
+        assert_eq!(state.google_fit.pending_drinks, [4]);
+        assert_eq!(state.paused_until, Some(1_715_000_000));
+        assert_eq!(state.reminder_schedule.len(), 2);
+    }
+
+    #[test]
+    fn newer_versions_are_refused_before_decoding() {
+        // The drink history of this file can't be decoded by this version of the app
+        let error =
+            parse_saved_data(include_bytes!("../tests/fixtures/history-v4.bin")).unwrap_err();
+
+        assert!(
+            matches!(
+                error,
+                LoadError::UnsupportedVersion {
+                    found: 4,
+                    supported: CURRENT_VERSION
+                }
+            ),
+            "{error}"
+        );
+    }
+
+    const RECOVERY_TIME: i64 = 1_715_000_000;
+
+    fn state_with_drinks(timestamps: &[i64]) -> InnerAppState {
+        let mut state = initial_app_state();
+        for timestamp in timestamps {
+            let time = DateTime::from_timestamp(*timestamp, 0).unwrap();
+            state.add_drink(DrinkPoint::new(250.0, beverage::WATER_ID, &time));
+        }
+
+        state
+    }
+
+    fn timestamps(state: &InnerAppState) -> Vec<i64> {
+        state
+            .drink_history
+            .iter()
+            .map(|drink| drink.timestamp)
+            .collect()
+    }
+
+    fn write_backup(dir: &Path, timestamp: i64, data: &[u8]) -> DateTime<Utc> {
+        let time = DateTime::from_timestamp(timestamp, 0).unwrap();
+        let backup_dir = dir.join(BACKUP_DIR_NAME);
+        std::fs::create_dir_all(&backup_dir).unwrap();
+        std::fs::write(
+            backup_dir.join(format!(
+                "history-{}.bin",
+                time.format(BACKUP_TIMESTAMP_FORMAT)
+            )),
+            data,
+        )
+        .unwrap();
+
+        time
+    }
+
+    /// Recovers from the unreadable `saved` file as done at startup, checking that it's kept
+    fn recover(dir: &Path, saved: &[u8]) -> (InnerAppState, RecoveryReport) {
+        let data_path = dir.join(DATA_FILE_NAME);
+        std::fs::write(&data_path, saved).unwrap();
+        let error = parse_saved_data(saved).unwrap_err();
+
+        let (state, report) = recover_app_state(
+            &data_path,
+            &dir.join(BACKUP_DIR_NAME),
+            &error,
+            DateTime::from_timestamp(RECOVERY_TIME, 0).unwrap(),
+        );
+
+        assert_eq!(report.reason, error.to_string());
+        assert!(!data_path.exists());
+        let corrupt_file = report.corrupt_file.as_ref().unwrap();
+        assert_eq!(std::fs::read(corrupt_file).unwrap(), saved);
+
+        (state, report)
+    }
+
+    #[test]
+    fn truncated_saves_are_restored_from_the_latest_readable_backup() {
+        let dir = test_dir("recover-backup");
+        let day = 24 * 60 * 60;
+        let backed_up = state_with_drinks(&[RECOVERY_TIME - 2 * day, RECOVERY_TIME - day]);
+        let backup_time = write_backup(&dir, RECOVERY_TIME - day, &serialize_app_state(&backed_up));
+        // A newer backup that's just as broken as the save file
+        write_backup(&dir, RECOVERY_TIME - 60, b"garbled");
+
+        let saved = serialize_app_state(&state_with_drinks(&[
+            RECOVERY_TIME - 2 * day,
+            RECOVERY_TIME - day,
+            RECOVERY_TIME - 60 * 60,
+        ]));
+        let (state, report) = recover(&dir, &saved[..saved.len() / 2]);
+
+        assert_eq!(report.restored_backup, Some(backup_time));
+        assert_eq!(report.salvaged_drinks, 0);
+        assert_eq!(timestamps(&state), timestamps(&backed_up));
+        assert_eq!(state.next_drink_id, 3);
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn readable_drinks_are_salvaged_without_a_backup() {
+        let dir = test_dir("recover-salvage");
+        let mut saved_state = state_with_drinks(&[RECOVERY_TIME - 7200, RECOVERY_TIME - 3600]);
+        saved_state.reminder_interval = 45 * 60;
+        saved_state.day_settings.timezone = Some("Qqqqqqq".to_owned());
+
+        // Break the timezone's text, leaving the drink history readable. It fills a single
+        // word, so its characters are packed as they are
+        let mut saved = serialize_app_state(&saved_state);
+        let timezone = saved
+            .windows(7)
+            .position(|window| window == b"Qqqqqqq")
+            .unwrap();
+        saved[timezone] = 0xFF;
+
+        let (state, report) = recover(&dir, &saved);
+
+        assert_eq!(report.restored_backup, None);
+        assert_eq!(report.salvaged_drinks, 2);
+        assert_eq!(timestamps(&state), timestamps(&saved_state));
+        let ids: Vec<u64> = state.drink_history.iter().map(|drink| drink.id).collect();
+        assert_eq!(ids, [1, 2]);
+
+        // Everything but the drinks starts over
+        assert_eq!(state.reminder_interval, DEFAULT_REMINDER_INTERVAL);
+        assert_eq!(state.day_settings.timezone, None);
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn unrecoverable_saves_start_over() {
+        let dir = test_dir("recover-nothing");
+        write_backup(&dir, RECOVERY_TIME - 60, b"garbled");
+
+        let (state, report) = recover(&dir, b"definitely not a save file");
+
+        assert_eq!(report.restored_backup, None);
+        assert_eq!(report.salvaged_drinks, 0);
+        assert!(state.drink_history.is_empty());
+        assert_eq!(state.version, CURRENT_VERSION);
+        assert_eq!(state.next_drink_id, 1);
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
 }
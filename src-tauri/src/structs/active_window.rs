@@ -0,0 +1,71 @@
+use chrono::{DateTime, Datelike, Days, Duration, NaiveDateTime, TimeZone};
+use serde::{Deserialize, Serialize};
+
+pub const MINUTES_IN_DAY: u16 = 24 * 60;
+
+/// A weekly time window in which reminders are allowed to be sent
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ActiveWindow {
+    /// Day of the week, starting from 0 = Monday
+    pub weekday: u8,
+
+    /// Minutes since local midnight when the window opens
+    pub start: u16,
+
+    /// Minutes since local midnight when the window closes (exclusive), up to 1440
+    pub end: u16,
+}
+
+impl ActiveWindow {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.weekday > 6 {
+            return Err(format!("Invalid weekday {}", self.weekday));
+        }
+        if self.start >= self.end || self.end > MINUTES_IN_DAY {
+            return Err(format!(
+                "Invalid time window {}-{} on weekday {}",
+                self.start, self.end, self.weekday
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Returns the `(start, end)` of this window on `time`'s day, if the window falls on that weekday
+    fn bounds_on(&self, time: NaiveDateTime) -> Option<(NaiveDateTime, NaiveDateTime)> {
+        if time.weekday().num_days_from_monday() != u32::from(self.weekday) {
+            return None;
+        }
+
+        let midnight = time.date().and_hms_opt(0, 0, 0)?;
+        Some((
+            midnight + Duration::minutes(i64::from(self.start)),
+            midnight + Duration::minutes(i64::from(self.end)),
+        ))
+    }
+}
+
+/// Returns the earliest time at or after `time` that falls inside one of the `schedule`'s windows,
+/// evaluated in `time`'s timezone. An empty schedule allows reminders at any time.
+pub fn next_active_time<Tz: TimeZone>(
+    schedule: &[ActiveWindow],
+    time: DateTime<Tz>,
+) -> DateTime<Tz> {
+    if schedule.is_empty() {
+        return time;
+    }
+
+    let naive_time = time.naive_local();
+    let timezone = time.timezone();
+
+    // Windows repeat weekly, so one whole week ahead is enough to find the next one
+    (0..=7)
+        .filter_map(|days| naive_time.checked_add_days(Days::new(days)))
+        .flat_map(|day| schedule.iter().filter_map(move |w| w.bounds_on(day)))
+        .filter(|(_, end)| naive_time < *end)
+        .map(|(start, _)| start.max(naive_time))
+        // Times skipped by a DST transition don't exist locally, so those are skipped as well
+        .filter_map(|next| timezone.from_local_datetime(&next).earliest())
+        .min()
+        .unwrap_or(time)
+}
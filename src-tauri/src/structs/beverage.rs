@@ -0,0 +1,107 @@
+use std::sync::LazyLock;
+
+use serde::{Deserialize, Serialize};
+
+/// ID of water, the beverage of drinks logged before beverages existed
+pub const WATER_ID: u32 = 0;
+
+/// User-defined beverages get IDs from here on, below are reserved for built-in ones
+pub const FIRST_CUSTOM_ID: u32 = 100;
+
+/// Upper bound of a hydration coefficient
+pub const MAX_HYDRATION: f64 = 2.0;
+
+/// Upper bound of the caffeine in a beverage, in milligrams per 100ml
+pub const MAX_CAFFEINE: f64 = 500.0;
+
+/// A kind of drink & how much it hydrates
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Beverage {
+    pub id: u32,
+    pub name: String,
+
+    /// Emoji shown next to the name
+    pub icon: String,
+
+    /// Hex color, as `#rrggbb`
+    pub color: String,
+
+    /// How much of a drink's volume counts towards hydration, 1 being as much as water
+    pub hydration: f64,
+
+    /// Milligrams of caffeine per 100ml
+    pub caffeine: f64,
+}
+
+fn built_in(
+    id: u32,
+    name: &str,
+    icon: &str,
+    color: &str,
+    hydration: f64,
+    caffeine: f64,
+) -> Beverage {
+    Beverage {
+        id,
+        name: name.to_owned(),
+        icon: icon.to_owned(),
+        color: color.to_owned(),
+        hydration,
+        caffeine,
+    }
+}
+
+pub static BUILT_IN_BEVERAGES: LazyLock<Vec<Beverage>> = LazyLock::new(|| {
+    vec![
+        built_in(WATER_ID, "Water", "💧", "#4fc3f7", 1.0, 0.0),
+        built_in(1, "Tea", "🍵", "#aed581", 0.9, 20.0),
+        built_in(2, "Coffee", "☕", "#8d6e63", 0.8, 40.0),
+        built_in(3, "Juice", "🧃", "#ffb74d", 0.85, 0.0),
+        built_in(4, "Milk", "🥛", "#eeeeee", 1.0, 0.0),
+        built_in(5, "Sparkling water", "🫧", "#80deea", 1.0, 0.0),
+    ]
+});
+
+/// The beverage with the given ID. Drinks of a beverage that was since removed count as water
+pub fn find(beverages: &[Beverage], id: u32) -> &Beverage {
+    beverages
+        .iter()
+        .find(|beverage| beverage.id == id)
+        .or_else(|| beverages.iter().find(|beverage| beverage.id == WATER_ID))
+        .unwrap_or(&BUILT_IN_BEVERAGES[0])
+}
+
+fn is_hex_color(color: &str) -> bool {
+    color.len() == 7 && color.starts_with('#') && color[1..].chars().all(|c| c.is_ascii_hexdigit())
+}
+
+impl Beverage {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.id < FIRST_CUSTOM_ID {
+            return Err(format!(
+                "Custom beverage IDs must be at least {FIRST_CUSTOM_ID}"
+            ));
+        }
+        if self.name.trim().is_empty() {
+            return Err("Beverage name can't be empty".to_string());
+        }
+        if !is_hex_color(&self.color) {
+            return Err(format!(
+                "Beverage color must be formatted as #rrggbb, got {}",
+                self.color
+            ));
+        }
+        if !(0.0..=MAX_HYDRATION).contains(&self.hydration) {
+            return Err(format!(
+                "Hydration coefficient must be between 0 and {MAX_HYDRATION}"
+            ));
+        }
+        if !(0.0..=MAX_CAFFEINE).contains(&self.caffeine) {
+            return Err(format!(
+                "Caffeine must be between 0 and {MAX_CAFFEINE}mg per 100ml"
+            ));
+        }
+
+        Ok(())
+    }
+}
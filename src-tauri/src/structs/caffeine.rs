@@ -0,0 +1,148 @@
+use serde::{Deserialize, Serialize};
+
+use crate::structs::active_window::MINUTES_IN_DAY;
+
+/// Upper bound of the caffeine in a single drink, in milligrams
+pub const MAX_DRINK_CAFFEINE: f64 = 1000.0;
+
+/// How caffeine is tracked & when to warn about it
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct CaffeineSettings {
+    /// Minutes it takes the body to get rid of half of the caffeine
+    pub half_life: u16,
+
+    /// Milligrams per day past which a warning is sent
+    pub daily_limit: f64,
+
+    /// Minutes since local midnight when the user goes to bed
+    pub bedtime: u16,
+
+    /// Minutes before bedtime from which drinking caffeine sends a warning, until the next day
+    /// starts. 0 disables it
+    pub bedtime_window: u16,
+
+    /// Whether to send warnings at all
+    pub warnings: bool,
+}
+
+impl CaffeineSettings {
+    pub const DEFAULT: Self = Self {
+        half_life: 5 * 60,
+        daily_limit: 400.0,
+        bedtime: 23 * 60,
+        bedtime_window: 6 * 60,
+        warnings: true,
+    };
+
+    pub fn validate(&self) -> Result<(), String> {
+        if !(30..=24 * 60).contains(&self.half_life) {
+            return Err("Caffeine half-life must be between 30 minutes and 24 hours".to_string());
+        }
+        if !(self.daily_limit > 0.0 && self.daily_limit <= 10.0 * MAX_DRINK_CAFFEINE) {
+            return Err(format!(
+                "Daily caffeine limit must be between 0 and {}mg",
+                10.0 * MAX_DRINK_CAFFEINE
+            ));
+        }
+        if self.bedtime >= MINUTES_IN_DAY {
+            return Err(format!("Invalid bedtime {}", self.bedtime));
+        }
+        if self.bedtime_window >= MINUTES_IN_DAY {
+            return Err("Bedtime warning window must be shorter than a day".to_string());
+        }
+
+        Ok(())
+    }
+
+    /// Whether a drink at `minute` since local midnight is too close to bedtime, or past it
+    /// before the day starting at `day_start` minutes since local midnight
+    pub fn is_near_bedtime(&self, minute: u16, day_start: u16) -> bool {
+        if self.bedtime_window == 0 {
+            return false;
+        }
+
+        // Minutes since the warning window opened, wrapping around midnight
+        let window_start = i32::from(self.bedtime) - i32::from(self.bedtime_window);
+        let since_window_start =
+            |minute: u16| (i32::from(minute) - window_start).rem_euclid(i32::from(MINUTES_IN_DAY));
+
+        // The window lasts until the day starts, unless the day starts before bedtime
+        let window_length = since_window_start(day_start).max(i32::from(self.bedtime_window));
+
+        since_window_start(minute) < window_length
+    }
+}
+
+/// Today's caffeine intake
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct CaffeineStatus {
+    /// Milligrams drank today
+    pub today: f64,
+
+    /// Estimated milligrams still in the body, across days
+    pub in_body: f64,
+
+    pub daily_limit: f64,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const fn at(hour: u16, minute: u16) -> u16 {
+        hour * 60 + minute
+    }
+
+    #[test]
+    fn warns_before_bedtime() {
+        // Bedtime at 11PM, warning from 5PM
+        let settings = CaffeineSettings::DEFAULT;
+
+        assert!(!settings.is_near_bedtime(at(16, 59), 0));
+        assert!(settings.is_near_bedtime(at(17, 0), 0));
+        assert!(settings.is_near_bedtime(at(22, 59), 0));
+    }
+
+    #[test]
+    fn keeps_warning_after_bedtime_until_the_day_starts() {
+        let settings = CaffeineSettings::DEFAULT;
+
+        assert!(settings.is_near_bedtime(at(23, 30), 0));
+        assert!(!settings.is_near_bedtime(at(0, 30), 0));
+
+        // Night owls' days start at 4AM
+        assert!(settings.is_near_bedtime(at(0, 30), at(4, 0)));
+        assert!(settings.is_near_bedtime(at(3, 59), at(4, 0)));
+        assert!(!settings.is_near_bedtime(at(4, 0), at(4, 0)));
+        assert!(!settings.is_near_bedtime(at(12, 0), at(4, 0)));
+    }
+
+    #[test]
+    fn bedtime_after_midnight() {
+        let settings = CaffeineSettings {
+            bedtime: at(1, 0),
+            bedtime_window: 4 * 60,
+            ..CaffeineSettings::DEFAULT
+        };
+
+        assert!(!settings.is_near_bedtime(at(20, 59), at(6, 0)));
+        assert!(settings.is_near_bedtime(at(21, 0), at(6, 0)));
+        assert!(settings.is_near_bedtime(at(3, 0), at(6, 0)));
+        assert!(!settings.is_near_bedtime(at(6, 0), at(6, 0)));
+
+        // Days starting before bedtime end the window at bedtime
+        assert!(settings.is_near_bedtime(at(0, 30), 0));
+        assert!(!settings.is_near_bedtime(at(1, 0), 0));
+    }
+
+    #[test]
+    fn empty_window_never_warns() {
+        let settings = CaffeineSettings {
+            bedtime_window: 0,
+            ..CaffeineSettings::DEFAULT
+        };
+
+        assert!(!settings.is_near_bedtime(at(23, 0), at(4, 0)));
+        assert!(!settings.is_near_bedtime(at(2, 0), at(4, 0)));
+    }
+}
@@ -0,0 +1,56 @@
+use serde::{Deserialize, Serialize};
+
+use crate::stats::Total;
+
+/// The amount of water to drink every day
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct DailyGoal {
+    /// Target amount in milliliters
+    pub amount: f64,
+
+    /// Whether to send a notification once the goal is reached
+    pub notify_when_reached: bool,
+}
+
+impl DailyGoal {
+    pub const DEFAULT: Self = Self {
+        amount: 2000.0,
+        notify_when_reached: true,
+    };
+
+    pub fn validate(&self) -> Result<(), String> {
+        if !(100.0..=10_000.0).contains(&self.amount) {
+            return Err("Daily goal must be between 100ml and 10000ml".to_string());
+        }
+
+        Ok(())
+    }
+}
+
+/// How far along today's drinks are towards the daily goal
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct DailyProgress {
+    pub goal: f64,
+
+    /// Effective hydration, which is what counts towards the goal
+    pub total: f64,
+
+    /// Raw volume drank
+    pub volume: f64,
+    pub remaining: f64,
+
+    /// Percentage of the goal reached, can go past 100
+    pub percentage: f64,
+}
+
+impl DailyProgress {
+    pub fn new(goal: f64, total: Total) -> Self {
+        Self {
+            goal,
+            total: total.hydration,
+            volume: total.volume,
+            remaining: (goal - total.hydration).max(0.0),
+            percentage: total.hydration / goal * 100.0,
+        }
+    }
+}
@@ -0,0 +1,122 @@
+use chrono::{DateTime, Duration, FixedOffset, Local, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::structs::{active_window::MINUTES_IN_DAY, drink_point::DrinkPoint};
+
+/// How drinks are split into days
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Default)]
+pub struct DaySettings {
+    /// IANA timezone name, e.g. `Asia/Jakarta`. Follows the system's timezone when unset
+    pub timezone: Option<String>,
+
+    /// Minutes after midnight when a new day starts, e.g. 240 for night owls going to bed at 4AM
+    pub day_start: u16,
+}
+
+impl DaySettings {
+    pub fn validate(&self) -> Result<(), String> {
+        if let Some(timezone) = &self.timezone {
+            timezone
+                .parse::<chrono_tz::Tz>()
+                .map_err(|_| format!("Unknown timezone {timezone}"))?;
+        }
+        if self.day_start >= MINUTES_IN_DAY {
+            return Err(format!("Invalid day start {}", self.day_start));
+        }
+
+        Ok(())
+    }
+
+    /// The configured timezone, or `None` to follow the system's
+    pub fn tz(&self) -> Option<chrono_tz::Tz> {
+        self.timezone
+            .as_deref()
+            .and_then(|timezone| timezone.parse::<chrono_tz::Tz>().ok())
+    }
+
+    /// `time` in the configured timezone
+    pub fn localize(&self, time: DateTime<Utc>) -> DateTime<FixedOffset> {
+        match self.tz() {
+            Some(timezone) => time.with_timezone(&timezone).fixed_offset(),
+            None => time.with_timezone(&Local).fixed_offset(),
+        }
+    }
+
+    /// The day `time` belongs to, taking the timezone and the day start into account
+    pub fn date_of(&self, time: DateTime<Utc>) -> NaiveDate {
+        self.date_at(self.localize(time))
+    }
+
+    /// The day a drink belongs to. Uses the timezone the drink was logged in rather than the
+    /// current one, so that travelling doesn't move past drinks to another day
+    pub fn date_of_drink(&self, drink: &DrinkPoint) -> NaiveDate {
+        let offset = FixedOffset::east_opt(drink.utc_offset)
+            .unwrap_or_else(|| FixedOffset::east_opt(0).unwrap());
+
+        self.date_at(
+            DateTime::from_timestamp(drink.timestamp, 0)
+                .unwrap_or_default()
+                .with_timezone(&offset),
+        )
+    }
+
+    fn date_at(&self, local_time: DateTime<FixedOffset>) -> NaiveDate {
+        (local_time.naive_local() - Duration::minutes(self.day_start.into())).date()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use super::*;
+
+    #[test]
+    fn drinks_keep_the_day_they_were_logged_on() {
+        let settings = DaySettings {
+            timezone: Some("America/New_York".to_owned()),
+            day_start: 0,
+        };
+        let jakarta = FixedOffset::east_opt(7 * 60 * 60).unwrap();
+        // Morning in Jakarta, still the previous evening in New York
+        let time = jakarta.with_ymd_and_hms(2024, 3, 10, 8, 0, 0).unwrap();
+        let drink = DrinkPoint::new(250.0, 0, &time);
+
+        assert_eq!(
+            settings.date_of(time.to_utc()),
+            NaiveDate::from_ymd_opt(2024, 3, 9).unwrap()
+        );
+        assert_eq!(
+            settings.date_of_drink(&drink),
+            NaiveDate::from_ymd_opt(2024, 3, 10).unwrap()
+        );
+    }
+
+    #[test]
+    fn drinks_before_the_day_start_count_towards_the_previous_day() {
+        let settings = DaySettings {
+            timezone: None,
+            day_start: 4 * 60,
+        };
+        let jakarta = FixedOffset::east_opt(7 * 60 * 60).unwrap();
+        let late_night = DrinkPoint::new(
+            250.0,
+            0,
+            &jakarta.with_ymd_and_hms(2024, 3, 10, 3, 59, 0).unwrap(),
+        );
+        let early_morning = DrinkPoint::new(
+            250.0,
+            0,
+            &jakarta.with_ymd_and_hms(2024, 3, 10, 4, 0, 0).unwrap(),
+        );
+
+        assert_eq!(
+            settings.date_of_drink(&late_night),
+            NaiveDate::from_ymd_opt(2024, 3, 9).unwrap()
+        );
+        assert_eq!(
+            settings.date_of_drink(&early_morning),
+            NaiveDate::from_ymd_opt(2024, 3, 10).unwrap()
+        );
+    }
+}
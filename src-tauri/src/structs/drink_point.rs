@@ -1,32 +1,67 @@
-use chrono::prelude::*;
-use {
-    chrono::Utc,
-    serde::{Deserialize, Serialize},
-};
+use chrono::{DateTime, Offset, TimeZone, Utc};
+use serde::{Deserialize, Serialize};
+
+use super::caffeine::MAX_DRINK_CAFFEINE;
 
 #[derive(Serialize, Deserialize, Debug, Clone, Copy)]
 pub struct DrinkPoint {
+    /// Stable identifier, assigned when the drink is added to the history
+    pub id: u64,
+
     /// Timestamp of when the drink was recorded
     pub timestamp: i64,
 
-    /// Amount of water drank in milliliters
+    /// Volume drank in milliliters
     pub amount: f64,
-}
 
-impl Default for DrinkPoint {
-    fn default() -> Self {
-        Self {
-            timestamp: Utc::now().timestamp(),
-            amount: 200.0,
-        }
-    }
+    /// Seconds east of UTC of the timezone the drink was logged in
+    pub utc_offset: i32,
+
+    /// ID of the beverage that was drunk
+    pub beverage: u32,
+
+    /// Milligrams of caffeine in the drink, overriding the beverage's own
+    pub caffeine: Option<f64>,
+
+    /// Whether the drink came from an import rather than being logged in the app. Imported
+    /// drinks were usually tracked elsewhere already, so they aren't uploaded to Google Fit
+    pub imported: bool,
 }
 
+/// Upper bound of a single drink, in milliliters
+pub const MAX_DRINK_AMOUNT: f64 = 5000.0;
+
 impl DrinkPoint {
-    pub fn new(amount: f64) -> Self {
+    pub fn new<Tz: TimeZone>(amount: f64, beverage: u32, time: &DateTime<Tz>) -> Self {
         Self {
-            timestamp: Utc::now().timestamp(),
+            id: 0,
+            timestamp: time.timestamp(),
             amount,
+            utc_offset: time.offset().fix().local_minus_utc(),
+            beverage,
+            caffeine: None,
+            imported: false,
+        }
+    }
+
+    pub fn validate(&self, now: DateTime<Utc>) -> Result<(), String> {
+        if !(self.amount > 0.0 && self.amount <= MAX_DRINK_AMOUNT) {
+            return Err(format!(
+                "Drink amount must be between 0 and {MAX_DRINK_AMOUNT}ml"
+            ));
+        }
+        if self
+            .caffeine
+            .is_some_and(|caffeine| !(0.0..=MAX_DRINK_CAFFEINE).contains(&caffeine))
+        {
+            return Err(format!(
+                "Caffeine must be between 0 and {MAX_DRINK_CAFFEINE}mg"
+            ));
         }
+        if self.timestamp > now.timestamp() {
+            return Err("Drinks can't be logged in the future".to_string());
+        }
+
+        Ok(())
     }
 }
@@ -0,0 +1,59 @@
+use std::collections::HashSet;
+
+use serde::{Deserialize, Serialize};
+
+use super::drink_point::DrinkPoint;
+
+/// Progress of uploading drinks to Google Fit
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct GoogleFitSync {
+    /// ID of the data source drinks are uploaded to. `None` when not connected
+    pub data_stream_id: Option<String>,
+
+    /// IDs of drinks waiting to be uploaded
+    pub pending_drinks: Vec<u64>,
+
+    /// Drinks with this ID or later haven't been queued for upload yet
+    pub next_queued_id: u64,
+}
+
+impl GoogleFitSync {
+    /// Queues the drinks of `history` added since the last call. Imported drinks are skipped, as
+    /// they may have come from Google Fit in the first place. Returns whether the queue changed
+    pub fn queue_new_drinks(&mut self, history: &[DrinkPoint], next_drink_id: u64) -> bool {
+        if self.data_stream_id.is_none() || self.next_queued_id == next_drink_id {
+            return false;
+        }
+
+        let next_queued_id = self.next_queued_id;
+        self.pending_drinks.extend(
+            history
+                .iter()
+                .filter(|drink| drink.id >= next_queued_id && !drink.imported)
+                .map(|drink| drink.id),
+        );
+        self.next_queued_id = next_drink_id;
+
+        true
+    }
+
+    /// The queued drinks, in the order of `history`. Drinks deleted while queued are
+    /// dropped from the queue
+    pub fn pending(&mut self, history: &[DrinkPoint]) -> Vec<DrinkPoint> {
+        let pending: HashSet<u64> = self.pending_drinks.drain(..).collect();
+        let drinks: Vec<DrinkPoint> = history
+            .iter()
+            .filter(|drink| pending.contains(&drink.id))
+            .copied()
+            .collect();
+        self.pending_drinks = drinks.iter().map(|drink| drink.id).collect();
+
+        drinks
+    }
+
+    /// Removes uploaded drinks from the queue
+    pub fn dequeue(&mut self, uploaded: &[DrinkPoint]) {
+        let uploaded: HashSet<u64> = uploaded.iter().map(|drink| drink.id).collect();
+        self.pending_drinks.retain(|id| !uploaded.contains(id));
+    }
+}
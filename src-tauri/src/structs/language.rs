@@ -0,0 +1,80 @@
+use std::sync::LazyLock;
+
+use serde::{Deserialize, Serialize};
+
+/// Language the tray & notifications are shown in
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Language {
+    #[serde(rename = "en")]
+    English,
+    #[serde(rename = "id")]
+    Indonesian,
+}
+
+/// Language of the OS, read once at startup
+static SYSTEM_LANGUAGE: LazyLock<Language> = LazyLock::new(|| {
+    sys_locale::get_locale()
+        .and_then(|locale| Language::from_code(&locale))
+        .unwrap_or(Language::English)
+});
+
+impl Language {
+    pub const ALL: [Self; 2] = [Self::English, Self::Indonesian];
+
+    /// ISO 639-1 code of the language
+    pub const fn code(self) -> &'static str {
+        match self {
+            Self::English => "en",
+            Self::Indonesian => "id",
+        }
+    }
+
+    /// Separates the whole part of a number from its decimals
+    pub const fn decimal_separator(self) -> char {
+        match self {
+            Self::English => '.',
+            Self::Indonesian => ',',
+        }
+    }
+
+    /// Formats a number with up to `decimals` decimals, e.g. `1.25` or `1,25`, using the
+    /// language's decimal separator
+    pub fn format_number(self, number: f64, decimals: usize) -> String {
+        let number = format!("{number:.decimals$}");
+        let number = if number.contains('.') {
+            number.trim_end_matches('0').trim_end_matches('.')
+        } else {
+            &number
+        };
+
+        number.replace('.', &self.decimal_separator().to_string())
+    }
+
+    /// The language of a locale or language code, e.g. `id-ID`, `en_US` or `en`
+    pub fn from_code(code: &str) -> Option<Self> {
+        let language = code.split(['-', '_', '.']).next()?.to_lowercase();
+        Self::ALL
+            .into_iter()
+            .find(|candidate| candidate.code() == language)
+    }
+
+    /// The OS' language if it's supported, English otherwise
+    pub fn system() -> Self {
+        *SYSTEM_LANGUAGE
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn numbers_use_the_language_decimal_separator() {
+        assert_eq!(Language::English.format_number(412.6, 0), "413");
+        assert_eq!(Language::English.format_number(250.5, 1), "250.5");
+        assert_eq!(Language::Indonesian.format_number(250.5, 1), "250,5");
+        // Trailing zeros are dropped, along with the separator if nothing's left after it
+        assert_eq!(Language::Indonesian.format_number(1.2, 2), "1,2");
+        assert_eq!(Language::Indonesian.format_number(400.0, 1), "400");
+    }
+}
@@ -1 +1,12 @@
+pub mod active_window;
+pub mod beverage;
+pub mod caffeine;
+pub mod daily_goal;
+pub mod day_settings;
 pub mod drink_point;
+pub mod google_fit_sync;
+pub mod language;
+pub mod repeat_policy;
+pub mod settings;
+pub mod tray_preset;
+pub mod volume_unit;
@@ -0,0 +1,30 @@
+use serde::{Deserialize, Serialize};
+
+/// What to do when a reminder is ignored
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RepeatPolicy {
+    /// Seconds between repeated reminders
+    pub interval: u32,
+
+    /// How many times a missed reminder is repeated. 0 gives up until tomorrow
+    pub max_repeats: u8,
+
+    /// Whether repeated reminders use more insistent wording and urgency
+    pub escalate: bool,
+}
+
+impl RepeatPolicy {
+    pub const DEFAULT: Self = Self {
+        interval: 30 * 60,
+        max_repeats: 3,
+        escalate: true,
+    };
+
+    pub fn validate(&self) -> Result<(), String> {
+        if !(60..=24 * 60 * 60).contains(&self.interval) {
+            return Err("Repeat interval must be between 1 minute and 24 hours".to_string());
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,61 @@
+use serde::{Deserialize, Serialize};
+
+use super::{drink_point::MAX_DRINK_AMOUNT, language::Language, volume_unit::VolumeUnit};
+
+/// General preferences that don't belong to a more specific section
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct Settings {
+    /// Milliliters logged by the "Drink" notification action & the beverages tray submenu
+    pub drink_amount: f64,
+
+    /// Milliliters logged by the "Sip" notification action
+    pub sip_amount: f64,
+
+    /// Whether a sound is played when a drink is logged
+    pub drink_sound: bool,
+
+    /// Whether a sound is played along with reminders
+    pub reminder_sound: bool,
+
+    /// Volume of the app's sounds, between 0 and 1
+    pub volume: f32,
+
+    /// Unit amounts are shown in
+    pub unit: VolumeUnit,
+
+    /// Language of the tray & notifications. Follows the OS when unset
+    pub language: Option<Language>,
+}
+
+impl Settings {
+    pub const DEFAULT: Self = Self {
+        drink_amount: 200.0,
+        sip_amount: 100.0,
+        drink_sound: true,
+        reminder_sound: true,
+        volume: 1.0,
+        unit: VolumeUnit::Milliliter,
+        language: None,
+    };
+
+    /// The language to show texts in, taking the OS' language into account
+    pub fn language(&self) -> Language {
+        self.language.unwrap_or_else(Language::system)
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        for amount in [self.drink_amount, self.sip_amount] {
+            if !(amount > 0.0 && amount <= MAX_DRINK_AMOUNT) {
+                return Err(format!(
+                    "Drink amounts must be between 0 and {MAX_DRINK_AMOUNT}ml"
+                ));
+            }
+        }
+
+        if !(0.0..=1.0).contains(&self.volume) {
+            return Err("Volume must be between 0 and 1".to_string());
+        }
+
+        Ok(())
+    }
+}
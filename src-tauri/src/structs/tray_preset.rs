@@ -0,0 +1,61 @@
+use serde::{Deserialize, Serialize};
+
+use super::{beverage::WATER_ID, drink_point::MAX_DRINK_AMOUNT};
+
+/// A tray menu item that logs a drink in one click
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct TrayPreset {
+    /// Identifies the preset's tray menu item
+    pub id: u32,
+    pub label: String,
+
+    /// Shown before the label
+    pub emoji: String,
+
+    /// Milliliters logged by the preset
+    pub amount: f64,
+
+    /// ID of the beverage logged by the preset
+    pub beverage: u32,
+}
+
+impl TrayPreset {
+    /// The presets shown until the user edits them, matching the tray's original items.
+    /// Their labels are message IDs, so they follow the language until the user renames them
+    pub fn defaults(drink_amount: f64, sip_amount: f64) -> Vec<Self> {
+        vec![
+            Self {
+                id: 1,
+                label: "preset-drink".to_owned(),
+                emoji: "🥛".to_owned(),
+                amount: drink_amount,
+                beverage: WATER_ID,
+            },
+            Self {
+                id: 2,
+                label: "preset-sip".to_owned(),
+                emoji: "💧".to_owned(),
+                amount: sip_amount,
+                beverage: WATER_ID,
+            },
+        ]
+    }
+
+    /// ID of the preset's tray menu item
+    pub fn menu_id(&self) -> String {
+        format!("preset-{}", self.id)
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        if self.label.trim().is_empty() {
+            return Err("Preset label can't be empty".to_string());
+        }
+        if !(self.amount > 0.0 && self.amount <= MAX_DRINK_AMOUNT) {
+            return Err(format!(
+                "Preset amount must be between 0 and {MAX_DRINK_AMOUNT}ml"
+            ));
+        }
+
+        Ok(())
+    }
+}
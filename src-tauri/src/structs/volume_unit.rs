@@ -0,0 +1,135 @@
+use serde::{Deserialize, Serialize};
+
+use super::language::Language;
+
+/// Unit amounts are shown in. Amounts are always stored in milliliters
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VolumeUnit {
+    #[serde(rename = "ml")]
+    Milliliter,
+    #[serde(rename = "l")]
+    Liter,
+    #[serde(rename = "fl_oz_us")]
+    UsFluidOunce,
+    #[serde(rename = "fl_oz_imp")]
+    ImperialFluidOunce,
+    #[serde(rename = "cup_us")]
+    UsCup,
+}
+
+impl VolumeUnit {
+    /// Milliliters in one of this unit
+    pub const fn milliliters(self) -> f64 {
+        match self {
+            Self::Milliliter => 1.0,
+            Self::Liter => 1000.0,
+            Self::UsFluidOunce => 29.573_529_562_5,
+            Self::ImperialFluidOunce => 28.413_062_5,
+            Self::UsCup => 236.588_236_5,
+        }
+    }
+
+    pub fn to_ml(self, amount: f64) -> f64 {
+        amount * self.milliliters()
+    }
+
+    pub fn from_ml(self, ml: f64) -> f64 {
+        ml / self.milliliters()
+    }
+
+    /// Decimals shown when formatting, enough to tell common drink sizes apart
+    const fn decimals(self) -> usize {
+        match self {
+            Self::Milliliter => 0,
+            Self::UsFluidOunce | Self::ImperialFluidOunce => 1,
+            Self::Liter | Self::UsCup => 2,
+        }
+    }
+
+    /// Formats an amount of milliliters in this unit, e.g. `1.25L` or `8.5 US fl oz`, using
+    /// the language's decimal separator
+    pub fn format(self, ml: f64, language: Language) -> String {
+        let amount = language.format_number(self.from_ml(ml), self.decimals());
+
+        match self {
+            Self::Milliliter => format!("{amount}ml"),
+            Self::Liter => format!("{amount}L"),
+            Self::UsFluidOunce => format!("{amount} US fl oz"),
+            Self::ImperialFluidOunce => format!("{amount} imp fl oz"),
+            Self::UsCup if amount == "1" => "1 cup".to_owned(),
+            Self::UsCup => format!("{amount} cups"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const UNITS: [VolumeUnit; 5] = [
+        VolumeUnit::Milliliter,
+        VolumeUnit::Liter,
+        VolumeUnit::UsFluidOunce,
+        VolumeUnit::ImperialFluidOunce,
+        VolumeUnit::UsCup,
+    ];
+
+    fn assert_close(actual: f64, expected: f64) {
+        assert!(
+            (actual - expected).abs() < 1e-9,
+            "expected {expected}, got {actual}"
+        );
+    }
+
+    #[test]
+    fn conversions_round_trip() {
+        for unit in UNITS {
+            for ml in [0.0, 1.0, 50.0, 236.6, 1234.5678] {
+                assert_close(unit.to_ml(unit.from_ml(ml)), ml);
+                assert_close(unit.from_ml(unit.to_ml(ml)), ml);
+            }
+        }
+    }
+
+    #[test]
+    fn converts_between_units() {
+        assert_close(VolumeUnit::Liter.to_ml(1.5), 1500.0);
+        assert_close(
+            VolumeUnit::UsFluidOunce.from_ml(VolumeUnit::UsCup.to_ml(1.0)),
+            8.0,
+        );
+        assert_close(VolumeUnit::ImperialFluidOunce.from_ml(568.261_25), 20.0);
+    }
+
+    #[test]
+    fn formats_amounts() {
+        let english = Language::English;
+        assert_eq!(VolumeUnit::Milliliter.format(249.6, english), "250ml");
+        assert_eq!(VolumeUnit::Liter.format(1250.0, english), "1.25L");
+        assert_eq!(VolumeUnit::Liter.format(2000.0, english), "2L");
+        assert_eq!(
+            VolumeUnit::UsFluidOunce.format(VolumeUnit::UsFluidOunce.to_ml(8.5), english),
+            "8.5 US fl oz"
+        );
+        assert_eq!(
+            VolumeUnit::ImperialFluidOunce.format(568.261_25, english),
+            "20 imp fl oz"
+        );
+        assert_eq!(VolumeUnit::UsCup.format(236.588_236_5, english), "1 cup");
+        assert_eq!(
+            VolumeUnit::UsCup.format(354.882_354_75, english),
+            "1.5 cups"
+        );
+    }
+
+    #[test]
+    fn formats_decimals_for_the_language() {
+        let indonesian = Language::Indonesian;
+        assert_eq!(VolumeUnit::Liter.format(1250.0, indonesian), "1,25L");
+        assert_eq!(VolumeUnit::Milliliter.format(1250.0, indonesian), "1250ml");
+        assert_eq!(
+            VolumeUnit::UsCup.format(354.882_354_75, indonesian),
+            "1,5 cups"
+        );
+    }
+}
@@ -0,0 +1,54 @@
+use std::{sync::Arc, time::Duration};
+
+use tauri::{AppHandle, Manager};
+use tokio::select;
+use tracing::{instrument, trace, warn};
+
+use crate::google_fit::{queue_new_drinks, upload_queued_drinks, FitError};
+
+/// Delay before retrying a failed upload, doubled after every failure
+const INITIAL_RETRY_DELAY: Duration = Duration::from_secs(60);
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(60 * 60);
+
+/// Queues new drinks for upload to Google Fit & uploads them, retrying failed uploads
+/// with an exponential backoff
+#[instrument(skip(app))]
+pub async fn upload_task(app: AppHandle) {
+    let notify = Arc::new(tokio::sync::Notify::new());
+
+    for event in ["drink", "google-fit-connected"] {
+        let notifier = notify.clone();
+        app.listen_global(event, move |_e| {
+            trace!("Received {event} event. Sending upload signal");
+            notifier.notify_one();
+        });
+    }
+
+    // Upload whatever was left queued by the last run
+    notify.notify_one();
+
+    let mut retry_delay = None;
+    loop {
+        match retry_delay {
+            Some(delay) => select! {
+                _ = tokio::time::sleep(delay) => {},
+                _ = notify.notified() => {},
+            },
+            None => notify.notified().await,
+        }
+
+        queue_new_drinks(&app);
+
+        retry_delay = match upload_queued_drinks(&app).await {
+            // Retrying won't help until the user connects again
+            Ok(()) | Err(FitError::NotConnected | FitError::Expired) => None,
+            Err(e) => {
+                let delay = retry_delay.map_or(INITIAL_RETRY_DELAY, |delay: Duration| {
+                    (delay * 2).min(MAX_RETRY_DELAY)
+                });
+                warn!("Unable to upload drinks to Google Fit, retrying in {delay:?}: {e}");
+                Some(delay)
+            }
+        };
+    }
+}
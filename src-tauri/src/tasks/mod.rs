@@ -1 +1,4 @@
+pub mod google_fit;
 pub mod notification;
+pub mod timing;
+pub mod tray;
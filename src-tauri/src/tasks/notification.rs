@@ -1,81 +1,249 @@
 use std::sync::Arc;
 
-use chrono::{Days, Duration, Local};
+use chrono::{DateTime, Duration, Local, Utc};
 use tauri::{AppHandle, Manager};
-use tokio::select;
+use tokio::{select, sync::Notify};
 use tracing::{instrument, trace};
 
 use crate::{
-    commands::create_drink_notification, storage::AppState, structs::drink_point::DrinkPoint,
+    clock::{Clock, SharedClock},
+    commands::send_drink_reminder,
+    storage::AppState,
+    tasks::timing::{next_reminder, Reminder, ReminderSettings},
 };
 
 #[instrument(skip(app))]
 pub async fn task_manager(app: AppHandle) {
     // A channel to short-circuit the notification task
-    let notify = Arc::new(tokio::sync::Notify::new());
+    let notify = Arc::new(Notify::new());
 
-    let notifier = notify.clone();
-    app.listen_global("drink", move |_e| {
-        trace!("Received drink event. Sending reschedule signal");
-        notifier.notify_one();
-    });
+    for event in [
+        "drink",
+        "interval-changed",
+        "schedule-changed",
+        "pause-changed",
+        "repeat-policy-changed",
+        "settings-changed",
+        "day-settings-changed",
+    ] {
+        let notifier = notify.clone();
+        app.listen_global(event, move |_e| {
+            trace!("Received {event} event. Sending reschedule signal");
+            notifier.notify_one();
+        });
+    }
+
+    let clock = app.state::<SharedClock>().inner().clone();
+
+    schedule_reminders(
+        clock.as_ref(),
+        &notify,
+        |sent_reminder, now| app_next_reminder(&app, sent_reminder, now),
+        |reminder| send_drink_reminder(&app, reminder.repeat),
+    )
+    .await;
+}
 
-    let notified = notify.clone();
+/// Sends reminders as they come due, starting over whenever `reschedule` is notified
+///
+/// `next` computes the reminder to wait for from the one sent last & the current time
+async fn schedule_reminders(
+    clock: &dyn Clock,
+    reschedule: &Notify,
+    mut next: impl FnMut(Option<Reminder>, DateTime<Utc>) -> Reminder,
+    mut send: impl FnMut(Reminder),
+) -> ! {
+    let mut sent_reminder = None;
     loop {
         trace!("Re-scheduling notification task");
         // Debounce 1s
         tokio::time::sleep(Duration::seconds(1).to_std().unwrap()).await;
 
+        let reminder = next(sent_reminder, clock.now());
         select! {
-            () = wait_next_notif(app.clone()) => {
-                create_drink_notification(app.clone());
+            () = sleep_until(clock, reminder.time) => {
+                send(reminder);
+                sent_reminder = Some(reminder);
                 trace!("Notification task completed, rescheduling");
             },
-            _ = notified.notified() => {
-                trace!("Received drink event, rescheduling notification task");
+            () = reschedule.notified() => {
+                trace!("Received reschedule signal, rescheduling notification task");
             },
         };
     }
 }
 
-#[instrument(skip(app))]
-async fn wait_next_notif(app: AppHandle) {
-    let last_drink_timestamp = {
-        let state = app.state::<AppState>();
-        let app_state = state.0.read().unwrap();
-        app_state
+/// The next reminder to send, according to the app's state
+fn app_next_reminder(
+    app: &AppHandle,
+    sent_reminder: Option<Reminder>,
+    now: DateTime<Utc>,
+) -> Reminder {
+    let state = app.state::<AppState>();
+    let app_state = state.0.read().unwrap();
+
+    let settings = ReminderSettings {
+        last_drink: app_state
             .drink_history
             .last()
-            .unwrap_or(&DrinkPoint::default())
-            .timestamp
+            .and_then(|drink| DateTime::from_timestamp(drink.timestamp, 0)),
+        interval: Duration::seconds(app_state.reminder_interval.into()),
+        schedule: &app_state.reminder_schedule,
+        paused_until: app_state
+            .paused_until
+            .and_then(|timestamp| DateTime::from_timestamp(timestamp, 0)),
+        repeat_policy: app_state.missed_reminder_policy,
     };
-    let last_drink_time = chrono::DateTime::from_timestamp(last_drink_timestamp, 0).unwrap();
-    let next_drink_time = last_drink_time + chrono::Duration::hours(1);
-    let time_difference = next_drink_time - chrono::Utc::now();
-
-    trace!("Seconds until next drink: {time_difference} seconds");
-
-    if time_difference.num_seconds() < 0 {
-        // If the time difference is negative, we've already passed the next drink time
-        // so we'll just wait indefinitely
-        //
-        // TODO: Handle this edge case in the future, maybe set an hourly / daily reminder
-
-        let start_day_tomorrow = chrono::Local::now()
-            .naive_local()
-            .checked_add_days(Days::new(1))
-            .unwrap()
-            .date()
-            .and_hms_opt(10, 0, 0)
-            .unwrap()
-            .and_local_timezone(chrono::Local)
-            .unwrap();
-        trace!("10AM Tomorrow: {start_day_tomorrow}");
-        let time_until_start_day_tomorrow = start_day_tomorrow - Local::now();
-        trace!("Seconds until 10AM tomorrow: {time_until_start_day_tomorrow}");
-
-        tokio::time::sleep(time_until_start_day_tomorrow.to_std().unwrap()).await;
-    } else {
-        tokio::time::sleep(time_difference.to_std().unwrap()).await;
+
+    // Days are counted in the configured timezone, keeping its DST changes
+    match app_state.day_settings.tz() {
+        Some(timezone) => next_reminder(&settings, sent_reminder, &now.with_timezone(&timezone)),
+        None => next_reminder(&settings, sent_reminder, &now.with_timezone(&Local)),
+    }
+}
+
+/// Sleeps until `clock` reaches `deadline`
+pub async fn sleep_until(clock: &dyn Clock, deadline: DateTime<Utc>) {
+    let time_difference = deadline - clock.now();
+    trace!("Sleeping until {deadline}, in {time_difference}");
+
+    tokio::time::sleep(time_difference.to_std().unwrap_or_default()).await;
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        cell::{Cell, RefCell},
+        future::Future,
+    };
+
+    use chrono::TimeZone;
+
+    use super::*;
+    use crate::{clock::FakeClock, structs::repeat_policy::RepeatPolicy};
+
+    fn start() -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2024, 5, 6, 10, 0, 0).unwrap()
+    }
+
+    /// Runs the reminder loop until `script` is done, returning when each reminder was sent
+    /// & its repeat. Reminders are due an hour after `last_drink` and repeated once
+    async fn sent_reminders(
+        clock: &FakeClock,
+        last_drink: &Cell<DateTime<Utc>>,
+        reschedule: &Notify,
+        script: impl Future<Output = ()>,
+    ) -> Vec<(DateTime<Utc>, u8)> {
+        let sent = RefCell::new(Vec::new());
+        let reminders = schedule_reminders(
+            clock,
+            reschedule,
+            |sent_reminder, now| {
+                let settings = ReminderSettings {
+                    last_drink: Some(last_drink.get()),
+                    interval: Duration::hours(1),
+                    schedule: &[],
+                    paused_until: None,
+                    repeat_policy: RepeatPolicy {
+                        interval: 30 * 60,
+                        max_repeats: 1,
+                        escalate: false,
+                    },
+                };
+                next_reminder(&settings, sent_reminder, &now)
+            },
+            |reminder| sent.borrow_mut().push((clock.now(), reminder.repeat)),
+        );
+
+        select! {
+            () = reminders => unreachable!("The reminder loop never ends"),
+            () = script => {},
+        }
+
+        sent.into_inner()
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn sleeps_until_the_clock_reaches_the_deadline() {
+        let clock = FakeClock::starting_at(start());
+
+        sleep_until(&clock, start() + Duration::minutes(90)).await;
+        assert_eq!(clock.now(), start() + Duration::minutes(90));
+
+        // Deadlines that have passed don't wait at all
+        sleep_until(&clock, start()).await;
+        assert_eq!(clock.now(), start() + Duration::minutes(90));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn sends_due_reminders_and_repeats_them() {
+        let clock = FakeClock::starting_at(start());
+        let last_drink = Cell::new(start());
+        let reschedule = Notify::new();
+
+        let sent = sent_reminders(&clock, &last_drink, &reschedule, async {
+            tokio::time::sleep(std::time::Duration::from_secs(3 * 60 * 60)).await;
+        })
+        .await;
+
+        // Out of repeats after the second one, so the next is only sent tomorrow
+        assert_eq!(
+            sent,
+            [
+                (start() + Duration::minutes(60), 0),
+                (start() + Duration::minutes(90), 1),
+            ]
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn reschedules_when_a_drink_is_logged() {
+        let clock = FakeClock::starting_at(start());
+        let last_drink = Cell::new(start());
+        let reschedule = Notify::new();
+
+        let sent = sent_reminders(&clock, &last_drink, &reschedule, async {
+            tokio::time::sleep(std::time::Duration::from_secs(30 * 60)).await;
+            last_drink.set(clock.now());
+            reschedule.notify_one();
+
+            tokio::time::sleep(std::time::Duration::from_secs(2 * 60 * 60)).await;
+        })
+        .await;
+
+        assert_eq!(
+            sent,
+            [
+                (start() + Duration::minutes(90), 0),
+                (start() + Duration::minutes(120), 1),
+            ]
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn drinks_logged_during_the_debounce_are_picked_up() {
+        let clock = FakeClock::starting_at(start());
+        let last_drink = Cell::new(start());
+        let reschedule = Notify::new();
+
+        let sent = sent_reminders(&clock, &last_drink, &reschedule, async {
+            // Half a second after the first reminder, while the loop is debouncing
+            tokio::time::sleep(std::time::Duration::from_millis(60 * 60 * 1000 + 500)).await;
+            last_drink.set(clock.now());
+            reschedule.notify_one();
+
+            tokio::time::sleep(std::time::Duration::from_secs(2 * 60 * 60)).await;
+        })
+        .await;
+
+        let drank_at = start() + Duration::minutes(60) + Duration::milliseconds(500);
+        assert_eq!(
+            sent,
+            [
+                (start() + Duration::minutes(60), 0),
+                (drank_at + Duration::minutes(60), 0),
+                (drank_at + Duration::minutes(90), 1),
+            ]
+        );
     }
 }
@@ -0,0 +1,266 @@
+use chrono::{DateTime, Days, Duration, TimeZone, Utc};
+
+use crate::structs::{
+    active_window::{next_active_time, ActiveWindow},
+    repeat_policy::RepeatPolicy,
+};
+
+/// Everything that decides when the next reminder is due
+#[derive(Debug, Clone)]
+pub struct ReminderSettings<'a> {
+    pub last_drink: Option<DateTime<Utc>>,
+    pub interval: Duration,
+    pub schedule: &'a [ActiveWindow],
+    pub paused_until: Option<DateTime<Utc>>,
+    pub repeat_policy: RepeatPolicy,
+}
+
+/// A reminder, either due or already sent. Used to repeat reminders that have been ignored
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Reminder {
+    /// The last drink at the time the reminder was scheduled
+    pub last_drink: Option<DateTime<Utc>>,
+    pub time: DateTime<Utc>,
+
+    /// How many times the reminder has been repeated, 0 for the first one
+    pub repeat: u8,
+}
+
+/// 10AM on the day after `now`, in `now`'s timezone
+pub fn start_of_day_after<Tz: TimeZone>(now: &DateTime<Tz>) -> DateTime<Tz> {
+    let start_of_day = now
+        .naive_local()
+        .checked_add_days(Days::new(1))
+        .unwrap()
+        .date()
+        .and_hms_opt(10, 0, 0)
+        .unwrap();
+
+    // 10AM only falls into a DST gap in a handful of historical timezones, where it's skipped ahead
+    let timezone = now.timezone();
+    timezone
+        .from_local_datetime(&start_of_day)
+        .earliest()
+        .or_else(|| {
+            timezone
+                .from_local_datetime(&(start_of_day + Duration::hours(1)))
+                .earliest()
+        })
+        .unwrap()
+}
+
+/// Computes the next reminder to send, given the reminder that was last sent
+///
+/// `now` carries the timezone that day boundaries and the schedule are evaluated in
+pub fn next_reminder<Tz: TimeZone>(
+    settings: &ReminderSettings,
+    last_sent: Option<Reminder>,
+    now: &DateTime<Tz>,
+) -> Reminder {
+    let now_utc = now.with_timezone(&Utc);
+    let last_drink = settings.last_drink.unwrap_or(now_utc);
+
+    let mut next_time = last_drink + settings.interval;
+    let mut repeat = 0;
+
+    // Reminders sent before the latest drink have been answered
+    let last_sent = last_sent.filter(|reminder| reminder.last_drink == settings.last_drink);
+
+    if let Some(paused_until) = settings.paused_until.filter(|until| *until > now_utc) {
+        // Reminders are held back until the snooze / pause is over
+        next_time = next_time.max(paused_until);
+    } else if next_time < now_utc {
+        // We've already passed the next drink time, so the reminder has been missed
+        match last_sent {
+            Some(reminder) if reminder.repeat < settings.repeat_policy.max_repeats => {
+                next_time =
+                    reminder.time + Duration::seconds(settings.repeat_policy.interval.into());
+                repeat = reminder.repeat + 1;
+            }
+            // Out of repeats (or the reminder was missed while the app wasn't running),
+            // so give up until tomorrow
+            _ => {
+                next_time = start_of_day_after(now).with_timezone(&Utc);
+            }
+        }
+    }
+
+    // Reminders outside of the schedule are deferred to the start of the next active window
+    let next_time = next_active_time(settings.schedule, next_time.with_timezone(&now.timezone()));
+
+    Reminder {
+        last_drink: settings.last_drink,
+        time: next_time.with_timezone(&Utc),
+        repeat,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{NaiveDate, Utc};
+    use chrono_tz::{Europe::Berlin, Tz};
+
+    use super::*;
+
+    const NO_REPEATS: RepeatPolicy = RepeatPolicy {
+        interval: 30 * 60,
+        max_repeats: 0,
+        escalate: false,
+    };
+
+    fn berlin(year: i32, month: u32, day: u32, hour: u32, minute: u32) -> DateTime<Tz> {
+        let time = NaiveDate::from_ymd_opt(year, month, day)
+            .unwrap()
+            .and_hms_opt(hour, minute, 0)
+            .unwrap();
+        Berlin.from_local_datetime(&time).earliest().unwrap()
+    }
+
+    /// When the reminder is due in Berlin, & its repeat
+    fn local(reminder: Reminder) -> (DateTime<Tz>, u8) {
+        (reminder.time.with_timezone(&Berlin), reminder.repeat)
+    }
+
+    fn settings(last_drink: DateTime<Tz>) -> ReminderSettings<'static> {
+        ReminderSettings {
+            last_drink: Some(last_drink.with_timezone(&Utc)),
+            interval: Duration::hours(1),
+            schedule: &[],
+            paused_until: None,
+            repeat_policy: NO_REPEATS,
+        }
+    }
+
+    #[test]
+    fn reminds_an_interval_after_the_last_drink() {
+        let settings = settings(berlin(2024, 5, 6, 12, 0));
+        let reminder = next_reminder(&settings, None, &berlin(2024, 5, 6, 12, 30));
+
+        assert_eq!(reminder.time, berlin(2024, 5, 6, 13, 0));
+        assert_eq!(reminder.repeat, 0);
+    }
+
+    #[test]
+    fn start_of_day_after_rolls_over_midnight() {
+        assert_eq!(
+            start_of_day_after(&berlin(2024, 5, 6, 23, 59)),
+            berlin(2024, 5, 7, 10, 0)
+        );
+        assert_eq!(
+            start_of_day_after(&berlin(2024, 12, 31, 0, 1)),
+            berlin(2025, 1, 1, 10, 0)
+        );
+    }
+
+    #[test]
+    fn start_of_day_after_follows_dst_transitions() {
+        // Clocks go forward to CEST (UTC+2) at 2AM on 31 March 2024
+        let spring = start_of_day_after(&berlin(2024, 3, 30, 22, 0));
+        assert_eq!(spring, berlin(2024, 3, 31, 10, 0));
+        assert_eq!(
+            spring.with_timezone(&Utc).to_rfc3339(),
+            "2024-03-31T08:00:00+00:00"
+        );
+
+        // ...and back to CET (UTC+1) at 3AM on 27 October 2024
+        let autumn = start_of_day_after(&berlin(2024, 10, 26, 22, 0));
+        assert_eq!(autumn, berlin(2024, 10, 27, 10, 0));
+        assert_eq!(
+            autumn.with_timezone(&Utc).to_rfc3339(),
+            "2024-10-27T09:00:00+00:00"
+        );
+    }
+
+    #[test]
+    fn reminders_due_after_midnight_stay_on_the_next_day() {
+        let settings = settings(berlin(2024, 5, 6, 23, 30));
+        let reminder = next_reminder(&settings, None, &berlin(2024, 5, 6, 23, 45));
+
+        assert_eq!(reminder.time, berlin(2024, 5, 7, 0, 30));
+    }
+
+    #[test]
+    fn schedule_is_evaluated_in_local_time_across_dst() {
+        // Sunday 31 March 2024, open from 9AM local time
+        let schedule = [ActiveWindow {
+            weekday: 6,
+            start: 9 * 60,
+            end: 12 * 60,
+        }];
+        let settings = ReminderSettings {
+            schedule: &schedule,
+            ..settings(berlin(2024, 3, 31, 1, 0))
+        };
+        let reminder = next_reminder(&settings, None, &berlin(2024, 3, 31, 1, 30));
+
+        assert_eq!(reminder.time, berlin(2024, 3, 31, 9, 0));
+        assert_eq!(reminder.time.to_rfc3339(), "2024-03-31T07:00:00+00:00");
+    }
+
+    #[test]
+    fn overdue_reminders_give_up_until_tomorrow() {
+        // The reminder was missed while the app wasn't running
+        let settings = settings(berlin(2024, 5, 6, 12, 0));
+        let reminder = next_reminder(&settings, None, &berlin(2024, 5, 6, 15, 0));
+
+        assert_eq!(reminder.time, berlin(2024, 5, 7, 10, 0));
+        assert_eq!(reminder.repeat, 0);
+    }
+
+    #[test]
+    fn overdue_reminders_repeat_until_out_of_repeats() {
+        let settings = ReminderSettings {
+            repeat_policy: RepeatPolicy {
+                max_repeats: 2,
+                ..NO_REPEATS
+            },
+            ..settings(berlin(2024, 5, 6, 12, 0))
+        };
+
+        let first = next_reminder(&settings, None, &berlin(2024, 5, 6, 12, 30));
+        assert_eq!(local(first), (berlin(2024, 5, 6, 13, 0), 0));
+
+        // Rescheduling happens after the debounce, so just after the reminder was sent
+        let after =
+            |reminder: Reminder| reminder.time.with_timezone(&Berlin) + Duration::seconds(1);
+
+        let second = next_reminder(&settings, Some(first), &after(first));
+        assert_eq!(local(second), (berlin(2024, 5, 6, 13, 30), 1));
+
+        let third = next_reminder(&settings, Some(second), &after(second));
+        assert_eq!(local(third), (berlin(2024, 5, 6, 14, 0), 2));
+
+        let gave_up = next_reminder(&settings, Some(third), &after(third));
+        assert_eq!(local(gave_up), (berlin(2024, 5, 7, 10, 0), 0));
+    }
+
+    #[test]
+    fn drinks_after_a_reminder_restart_the_interval() {
+        let settings = ReminderSettings {
+            repeat_policy: RepeatPolicy::DEFAULT,
+            ..settings(berlin(2024, 5, 6, 12, 0))
+        };
+        let sent = next_reminder(&settings, None, &berlin(2024, 5, 6, 12, 30));
+
+        // A drink logged while the reminder is being rescheduled answers it
+        let settings = ReminderSettings {
+            last_drink: Some(berlin(2024, 5, 6, 13, 0).with_timezone(&Utc)),
+            ..settings
+        };
+        let reminder = next_reminder(&settings, Some(sent), &berlin(2024, 5, 6, 13, 0));
+
+        assert_eq!(reminder.time, berlin(2024, 5, 6, 14, 0));
+        assert_eq!(reminder.repeat, 0);
+    }
+
+    #[test]
+    fn pauses_hold_back_reminders() {
+        let settings = ReminderSettings {
+            paused_until: Some(berlin(2024, 5, 6, 15, 0).with_timezone(&Utc)),
+            ..settings(berlin(2024, 5, 6, 12, 0))
+        };
+        let reminder = next_reminder(&settings, None, &berlin(2024, 5, 6, 12, 30));
+
+        assert_eq!(reminder.time, berlin(2024, 5, 6, 15, 0));
+    }
+}
@@ -0,0 +1,35 @@
+use tauri::{AppHandle, Manager};
+use tracing::{instrument, trace};
+
+use crate::{clock::SharedClock, storage::AppState};
+
+/// Keeps the time left on the tray's pause status up to date, and resumes reminders once
+/// the pause is over
+#[instrument(skip(app))]
+pub async fn pause_status_updater(app: AppHandle) {
+    let clock = app.state::<SharedClock>().inner().clone();
+
+    loop {
+        let (paused_until, language) = {
+            let state = app.state::<AppState>();
+            let app_state = state.0.read().unwrap();
+            (app_state.paused_until, app_state.settings.language())
+        };
+
+        if let Some(paused_until) = paused_until {
+            let now = clock.now();
+
+            if paused_until <= now.timestamp() {
+                trace!("Pause is over, resuming reminders");
+                crate::set_paused_until(&app, None);
+            } else {
+                app.tray_handle()
+                    .get_item("pause-status")
+                    .set_title(crate::pause_status_label(paused_until, now, language))
+                    .ok();
+            }
+        }
+
+        tokio::time::sleep(std::time::Duration::from_secs(60)).await;
+    }
+}
@@ -9,7 +9,7 @@ import { UnlistenFn, listen } from '@tauri-apps/api/event';
 
 const cal: CalHeatmap = new CalHeatmap();
 
-type ListDrinksGroupDayReturnType = Record<string, number>
+type ListDrinksGroupDayReturnType = Record<string, { volume: number, hydration: number }>
 
 export const Heatmap = () => {
   const [unlistenDrink, setUnlistenDrink] = createSignal<UnlistenFn>()
@@ -36,7 +36,7 @@ export const Heatmap = () => {
       data: {
         source: processed_data, 
         x: p => p[0],
-        y : p => p[1],
+        y : p => p[1].hydration,
         defaultValue: 0
       },
       range: 3,
@@ -1,7 +1,11 @@
 
 export interface DrinkPoint {
+  id: number;
   timestamp: number;
   amount: number;
+  utc_offset: number;
+  beverage: number;
+  caffeine: number | null;
 }
 
 export type DrinkHistory = DrinkPoint[];